@@ -1,21 +1,66 @@
 //! Common functionality and types.
 //! Borrowed from Trunk!
 
+use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::fs::Metadata;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Context, Result};
 use once_cell::sync::Lazy;
 use tokio::fs;
 use tokio::process::Command;
+use walkdir::{DirEntry, WalkDir};
 
 static CWD: Lazy<PathBuf> =
     Lazy::new(|| std::env::current_dir().expect("error getting current dir"));
 
+/// The files Packler wrote recently, see [`record_write`].
+static RECENT_WRITES: Lazy<Mutex<HashMap<PathBuf, Instant>>> = Lazy::new(Default::default);
+
+/// How long after a write its watcher events are ignored.
+pub const SELF_WRITE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Remember that Packler just wrote `path`, so the watcher does not rebuild
+/// because of it. See [`is_recent_write`].
+pub fn record_write(path: &Path) {
+    let Ok(path) = std::path::absolute(path) else {
+        return;
+    };
+    let mut writes = RECENT_WRITES.lock().unwrap_or_else(PoisonError::into_inner);
+    writes.retain(|_, written| written.elapsed() < SELF_WRITE_WINDOW);
+    writes.insert(path, Instant::now());
+}
+
+/// Whether Packler wrote `path` less than [`SELF_WRITE_WINDOW`] ago.
+pub fn is_recent_write(path: &Path) -> bool {
+    let Ok(path) = std::path::absolute(path) else {
+        return false;
+    };
+    RECENT_WRITES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&path)
+        .is_some_and(|written| written.elapsed() < SELF_WRITE_WINDOW)
+}
+
+/// Run `future` to completion on a new runtime, for the blocking API: the
+/// callers do not need tokio.
+///
+/// This panics if called from within a runtime.
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Cannot start the async runtime")
+        .block_on(future)
+}
+
 /// Checks if path exists.
 pub async fn path_exists(path: impl AsRef<Path>) -> Result<bool> {
     fs::metadata(path.as_ref())
@@ -59,6 +104,31 @@ pub async fn is_executable(path: impl AsRef<Path>) -> Result<bool> {
         .with_context(|| format!("error checking file mode for file {:?}", path.as_ref()))
 }
 
+/// The paths are used in URLs (and object keys): always use `/`, whatever the
+/// platform.
+pub fn to_slash(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Walk `dir` recursively. Unless `include_hidden` is set, the hidden entries
+/// (whose name starts with a `.`) are skipped, and the hidden directories are
+/// not walked into.
+pub fn walk_dir(
+    dir: impl AsRef<Path>,
+    include_hidden: bool,
+) -> impl Iterator<Item = walkdir::Result<DirEntry>> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(move |entry| include_hidden || entry.depth() == 0 || !is_hidden(entry))
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
 /// Strip the CWD prefix from the given path.
 ///
 /// Returns `target` unmodified if an error is returned from the operation.
@@ -75,10 +145,21 @@ pub async fn run_command(
     name: &str,
     path: &Path,
     args: &[impl AsRef<OsStr> + Debug],
+) -> Result<()> {
+    run_command_with_env(name, path, args, &[] as &[(&str, &OsStr)]).await
+}
+
+/// Same as [`run_command`] but with additional environment variables.
+pub async fn run_command_with_env(
+    name: &str,
+    path: &Path,
+    args: &[impl AsRef<OsStr> + Debug],
+    envs: &[(impl AsRef<OsStr>, impl AsRef<OsStr>)],
 ) -> Result<()> {
     log::debug!("Run external binary: {name} (bin: {path:?})");
     let status = Command::new(path)
         .args(args)
+        .envs(envs.iter().map(|(key, value)| (key, value)))
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
         .spawn()
@@ -102,3 +183,150 @@ pub async fn run_command(
     }
     Ok(())
 }
+
+/// The cargo running the xtask, if any, so the same toolchain is used.
+pub fn cargo_path() -> PathBuf {
+    std::env::var_os("CARGO")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("cargo"))
+}
+
+/// Same as [`run_command`] but stdout and stderr are captured instead of
+/// forwarded. Stderr is logged as a warning on success. On failure, both are
+/// part of the error, a [`CommandFailed`].
+pub async fn run_command_captured(
+    name: &str,
+    path: &Path,
+    args: &[impl AsRef<OsStr> + Debug],
+) -> Result<()> {
+    log::debug!("Run external binary: {name} (bin: {path:?})");
+    let output = Command::new(path)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .with_context(|| {
+            log::error!("error during {} call", name);
+            format!("error during {} call", name)
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+    if !output.status.success() {
+        return Err(CommandFailed {
+            name: name.to_owned(),
+            status: output.status,
+            stdout,
+            stderr,
+        }
+        .into());
+    }
+    if !stdout.is_empty() {
+        log::debug!("{name}: {stdout}");
+    }
+    if !stderr.is_empty() {
+        log::warn!("{name}: {stderr}");
+    }
+    Ok(())
+}
+
+/// A command run by [`run_command_captured`] returned a bad status.
+#[derive(Debug)]
+pub struct CommandFailed {
+    pub name: String,
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl std::error::Error for CommandFailed {}
+
+impl Display for CommandFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Some tools report their errors on stdout.
+        let output = if self.stderr.is_empty() {
+            &self.stdout
+        } else {
+            &self.stderr
+        };
+        write!(f, "{} call returned a bad status:\n{output}", self.name)
+    }
+}
+
+/// Run a global command with the given arguments and return what it wrote on
+/// stdout. Stderr is still forwarded to the user. If the command fails an error
+/// is returned.
+pub async fn run_command_output(
+    name: &str,
+    path: &Path,
+    args: &[impl AsRef<OsStr> + Debug],
+) -> Result<String> {
+    log::debug!("Run external binary: {name} (bin: {path:?})");
+    let output = Command::new(path)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+        .await
+        .with_context(|| {
+            log::error!("error during {} call", name);
+            format!("error during {} call", name)
+        })?;
+    if !output.status.success() {
+        log::error!("{} call returned a bad status", name);
+        bail!("{} call returned a bad status", name);
+    }
+    String::from_utf8(output.stdout).with_context(|| format!("{} output is not valid UTF-8", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walk_dir_skips_hidden_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join(".git").join("HEAD"), "").unwrap();
+        std::fs::write(dir.path().join(".DS_Store"), "").unwrap();
+        std::fs::write(dir.path().join("logo.png"), "").unwrap();
+
+        let files = |include_hidden| {
+            walk_dir(dir.path(), include_hidden)
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_file())
+                .count()
+        };
+
+        assert_eq!(files(false), 1);
+        assert_eq!(files(true), 3);
+    }
+
+    #[test]
+    fn recent_writes_are_remembered() {
+        let dir = tempfile::tempdir().unwrap();
+        let written = dir.path().join("app.css");
+
+        assert!(!is_recent_write(&written));
+        record_write(&written);
+        assert!(is_recent_write(&written));
+        assert!(!is_recent_write(&dir.path().join("app.scss")));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failed_commands_report_their_stderr() {
+        let sh = Path::new("/bin/sh");
+        let e = run_command_captured("sh", sh, &["-c", "echo 'Error: oops' >&2; exit 1"])
+            .await
+            .unwrap_err();
+        assert!(e.to_string().ends_with("bad status:\nError: oops"));
+        let failed = e.downcast_ref::<CommandFailed>().unwrap();
+        assert_eq!(failed.stderr, "Error: oops");
+        assert_eq!(failed.status.code(), Some(1));
+
+        run_command_captured("sh", sh, &["-c", "echo 'Warning: meh' >&2"])
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+//! The errors a [`crate::Run`] can fail with.
+
+use crate::{
+    config, lock,
+    pipelines::{assets, backend, frontend},
+};
+use std::fmt::Display;
+
+/// The crate-wide [`Error`], named like the modules' ones: this alias tells it
+/// apart from them, e.g., `packler::PacklerError`.
+pub type PacklerError = Error;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The given component does not exist, with the valid ones.
+    UnknownComponent {
+        name: String,
+        valid: Vec<String>,
+    },
+    /// The action is not implemented (yet).
+    NotImplemented,
+    Config(config::Error),
+    Lock(lock::Error),
+    Assets(assets::Error),
+    Backend(backend::Error),
+    Frontend(frontend::Error),
+    Watch(notify::Error),
+    /// Packler was built without the `deploy` feature: there is no bucket to
+    /// deploy the assets to.
+    DeployNotEnabled,
+    CannotRenderConfig(Box<dyn std::error::Error>),
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::UnknownComponent { .. } | Self::NotImplemented | Self::DeployNotEnabled => None,
+            Self::Config(source) => Some(source),
+            Self::Lock(source) => Some(source),
+            Self::Assets(source) => Some(source),
+            Self::Backend(source) => Some(source),
+            Self::Frontend(source) => Some(source),
+            Self::Watch(source) => Some(source),
+            Self::CannotRenderConfig(source) => Some(source.as_ref()),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownComponent { name, valid } => write!(
+                f,
+                "Component '{name}' does not exist, expected one of: {}",
+                valid.join(", ")
+            ),
+            Self::NotImplemented => write!(f, "This action is not implemented yet"),
+            Self::Config(source) => write!(f, "Invalid configuration: {source}"),
+            Self::Lock(source) => write!(f, "{source}"),
+            Self::Assets(source) => write!(f, "{source}"),
+            Self::Backend(source) => write!(f, "{source}"),
+            Self::Frontend(source) => write!(f, "{source}"),
+            Self::Watch(source) => match crate::watch::error_hint(source) {
+                Some(hint) => write!(f, "Cannot watch the sources: {source}. {hint}"),
+                None => write!(f, "Cannot watch the sources: {source}"),
+            },
+            Self::DeployNotEnabled => write!(
+                f,
+                "Cannot deploy the assets: packler was built without the `deploy` feature"
+            ),
+            Self::CannotRenderConfig(source) => {
+                write!(f, "Could not render the configuration: {source}")
+            }
+        }
+    }
+}
+
+impl From<config::Error> for Error {
+    fn from(source: config::Error) -> Self {
+        Self::Config(source)
+    }
+}
+
+impl From<lock::Error> for Error {
+    fn from(source: lock::Error) -> Self {
+        Self::Lock(source)
+    }
+}
+
+impl From<assets::Error> for Error {
+    fn from(source: assets::Error) -> Self {
+        Self::Assets(source)
+    }
+}
+
+impl From<backend::Error> for Error {
+    fn from(source: backend::Error) -> Self {
+        Self::Backend(source)
+    }
+}
+
+impl From<frontend::Error> for Error {
+    fn from(source: frontend::Error) -> Self {
+        Self::Frontend(source)
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(source: notify::Error) -> Self {
+        Self::Watch(source)
+    }
+}
@@ -0,0 +1,163 @@
+//! Hooks are shell commands run at specific points of the pipelines. They are
+//! run with `sh -c` (`cmd /C` on Windows), and receive the following
+//! environment variables:
+//!
+//! - `PACKLER_DIST_DIR`: the [`PacklerConfig::dist_dir`]
+//! - `PACKLER_METADATA_FILE`: the [`PacklerConfig::metadata_file`]
+
+use crate::{common, PacklerConfig};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, path::Path};
+
+/// What to do when a hook fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HookFailure {
+    /// Fail the build (or the deploy).
+    #[default]
+    Fail,
+    /// Log a warning and continue.
+    Warn,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandHooks {
+    /// Run before the assets are built.
+    pub pre_build: Option<String>,
+
+    /// Run once the assets are built and the metadata file is written.
+    pub post_build: Option<String>,
+
+    /// Run once the assets are uploaded.
+    pub post_deploy: Option<String>,
+
+    /// Default: [`HookFailure::Fail`]
+    pub on_failure: HookFailure,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    PreBuild,
+    PostBuild,
+    PostDeploy,
+}
+
+impl Display for Hook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PreBuild => write!(f, "pre_build"),
+            Self::PostBuild => write!(f, "post_build"),
+            Self::PostDeploy => write!(f, "post_deploy"),
+        }
+    }
+}
+
+impl CommandHooks {
+    fn command(&self, hook: Hook) -> Option<&str> {
+        match hook {
+            Hook::PreBuild => self.pre_build.as_deref(),
+            Hook::PostBuild => self.post_build.as_deref(),
+            Hook::PostDeploy => self.post_deploy.as_deref(),
+        }
+    }
+}
+
+/// Run the command of `hook`, if any.
+///
+/// Returns an error only if the hook failed and [`CommandHooks::on_failure`]
+/// is [`HookFailure::Fail`].
+pub async fn run_hook(config: &PacklerConfig, hook: Hook) -> anyhow::Result<()> {
+    let Some(command) = config.hooks.command(hook) else {
+        return Ok(());
+    };
+
+    if config.dry_run {
+        info!("DRY RUN: would run {hook} hook: '{command}'");
+        return Ok(());
+    }
+
+    info!("Running {hook} hook: '{command}'");
+
+    let (shell, flag) = if cfg!(target_os = "windows") {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let envs = [
+        ("PACKLER_DIST_DIR", config.dist_dir.clone()),
+        ("PACKLER_METADATA_FILE", config.metadata_file()),
+    ];
+    let res =
+        common::run_command_with_env(&hook.to_string(), Path::new(shell), &[flag, command], &envs)
+            .await;
+
+    match (res, config.hooks.on_failure) {
+        (Ok(()), _) => Ok(()),
+        (Err(e), HookFailure::Warn) => {
+            warn!("{hook} hook failed: {e}");
+            Ok(())
+        }
+        (Err(e), HookFailure::Fail) => Err(e),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn config(dir: &Path, post_build: &str) -> PacklerConfig {
+        PacklerConfig {
+            dist_dir: dir.to_owned(),
+            hooks: CommandHooks {
+                post_build: Some(post_build.to_owned()),
+                ..CommandHooks::default()
+            },
+            ..PacklerConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failing_hook_fails_unless_warn() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = config(dir.path(), "exit 1");
+
+        assert!(run_hook(&config, Hook::PostBuild).await.is_err());
+        // Not configured.
+        assert!(run_hook(&config, Hook::PreBuild).await.is_ok());
+
+        config.hooks.on_failure = HookFailure::Warn;
+        assert!(run_hook(&config, Hook::PostBuild).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn hooks_get_the_packler_env_vars() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(
+            dir.path(),
+            r#"printf '%s\n%s' "$PACKLER_DIST_DIR" "$PACKLER_METADATA_FILE" > "$PACKLER_DIST_DIR/env""#,
+        );
+
+        run_hook(&config, Hook::PostBuild).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("env")).unwrap(),
+            format!(
+                "{}\n{}",
+                config.dist_dir.display(),
+                config.metadata_file().display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_skips_the_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            dry_run: true,
+            ..config(dir.path(), r#"touch "$PACKLER_DIST_DIR/ran""#)
+        };
+
+        run_hook(&config, Hook::PostBuild).await.unwrap();
+        assert!(!dir.path().join("ran").exists());
+    }
+}
@@ -1,22 +1,35 @@
+#[cfg(feature = "deploy")]
+use crate::pipelines::assets::{deploy_assets, deploy_files, prune_assets, verify_assets};
 use crate::{
     cli::build_parser,
-    pipelines::assets::{build_assets, deploy_assets},
+    lock::BuildLock,
+    pipelines::assets::{build_assets, write_reload_token},
 };
-pub use config::{PacklerConfig, PacklerParams};
+use clap::error::ErrorKind;
+pub use config::{ConfigFormat, PacklerConfig, PacklerParams};
+pub use error::{Error, PacklerError};
+use futures_util::future::try_join_all;
 use lazy_static::lazy_static;
-use log::{debug, info, trace};
-use notify::{RecommendedWatcher, RecursiveMode, Watcher};
-use pipelines::assets::clean_assets;
-use std::{
-    fmt::Display,
-    path::{Path, PathBuf},
-    time::{Duration, Instant},
+use log::{debug, error, info, warn};
+pub use logger::{init_logger, init_logger_with_verbosity, LogStyle, Verbosity};
+use pipelines::{
+    assets::{clean_assets, read_metadata_file, remove_dist_dir, sass},
+    backend::{build_backend, clean_backend},
+    frontend::{build_frontend, clean_frontend},
 };
+use std::{ffi::OsString, future::Future, path::PathBuf, time::Duration};
+use watch::ChangeFilter;
 
 pub mod common;
 pub mod config;
+pub mod error;
+pub mod hooks;
+pub mod lock;
+pub mod logger;
 pub mod pipelines;
+pub mod progress;
 pub mod tools;
+pub mod watch;
 
 /// Fetch the metadata of the crate.
 pub(crate) fn cargo_metadata() -> &'static cargo_metadata::Metadata {
@@ -30,33 +43,76 @@ pub(crate) fn cargo_metadata() -> &'static cargo_metadata::Metadata {
 }
 
 #[derive(Debug)]
-enum Error {
-    /// The given component does not exist.
-    UnknownComponent(String),
-}
+pub struct BuildOpts {
+    pub watch: bool,
 
-impl std::error::Error for Error {}
+    /// Only build the assets that changed since this git ref.
+    pub since: Option<String>,
 
-impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::UnknownComponent(component) => {
-                write!(f, "Component '{component}' does not exist")
-            }
-        }
-    }
+    pub profile: Profile,
+}
+
+/// How the assets are built.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Readable outputs, e.g. expanded CSS.
+    #[default]
+    Debug,
+    /// Minified outputs, e.g. compressed CSS.
+    Release,
 }
 
 #[derive(Debug)]
-pub struct BuildOpts {
+pub struct CleanOpts {
+    /// Remove the whole dist directory, not only what Packler wrote in it.
+    pub all: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct DeployOpts {
+    /// Deploy the component(s) again whenever their sources change, e.g., for
+    /// a live preview environment.
     pub watch: bool,
+
+    /// Verify the uploaded assets once deployed. Only a random sample of this
+    /// size is checked, or all of them if `0`.
+    pub verify: Option<usize>,
+
+    /// Skip the assets already uploaded by an interrupted deploy.
+    pub resume: bool,
+
+    /// How many times a failed upload is retried.
+    pub max_upload_retries: u32,
+
+    /// Fail the deploy if it takes longer than this.
+    pub deploy_timeout: Option<Duration>,
+
+    /// Only upload these (already built) files, then the metadata file.
+    pub files: Vec<PathBuf>,
+
+    /// Look for the stale objects of the bucket once deployed.
+    pub prune: Option<PruneOpts>,
+}
+
+#[derive(Debug)]
+pub struct PruneOpts {
+    /// How many previous versions of each asset are kept, e.g., for the
+    /// instances still running during a rollout.
+    pub keep_versions: usize,
+
+    /// Delete the stale objects, they are only listed otherwise.
+    pub delete: bool,
 }
 
 #[derive(Debug)]
 pub enum Action {
     Build(BuildOpts),
-    Clean,
-    Deploy,
+    Clean(CleanOpts),
+    /// Print the resolved configuration.
+    Config(ConfigFormat),
+    /// Print the completion script of the CLI for a shell.
+    Completions(clap_complete::Shell),
+    Deploy(DeployOpts),
     Unknown,
 }
 
@@ -66,18 +122,65 @@ pub enum Component {
     /// Assets are the things that should be served but that are not code per
     /// se. This includes images, CSS
     Assets,
+    /// One of the [`PacklerParams::frontend_crates`].
     Frontend(String),
 }
 
 impl Component {
-    fn new<S: AsRef<str>>(value: S) -> Result<Self, Error> {
-        match value.as_ref().to_lowercase().as_str() {
-            "backend" => Ok(Component::Backend),
-            "assets" => Ok(Component::Assets),
-            "frontend" => Ok(Component::Frontend("FIXME".to_owned())),
-            unknown => Err(Error::UnknownComponent(unknown.to_owned())),
+    /// `frontend` is all the frontend crates, a single one can be selected by
+    /// its name.
+    fn new<S: AsRef<str>>(value: S, params: &PacklerParams) -> Result<Vec<Self>, Error> {
+        let value = value.as_ref();
+        match value.to_lowercase().as_str() {
+            "backend" => Ok(vec![Component::Backend]),
+            "assets" => Ok(vec![Component::Assets]),
+            "frontend" => Ok(Self::frontends(params)),
+            _ if params.frontend_crates.iter().any(|name| name == value) => {
+                Ok(vec![Component::Frontend(value.to_owned())])
+            }
+            _ => {
+                let mut valid = vec![
+                    "backend".to_owned(),
+                    "assets".to_owned(),
+                    "frontend".to_owned(),
+                ];
+                valid.extend(params.frontend_crates.iter().cloned());
+                Err(Error::UnknownComponent {
+                    name: value.to_owned(),
+                    valid,
+                })
+            }
         }
     }
+
+    fn frontends(params: &PacklerParams) -> Vec<Self> {
+        params
+            .frontend_crates
+            .iter()
+            .cloned()
+            .map(Component::Frontend)
+            .collect()
+    }
+}
+
+/// The directory of the Cargo.toml of the workspace crate `crate_name`.
+fn crate_dir(crate_name: &str) -> Option<PathBuf> {
+    cargo_metadata()
+        .workspace_packages()
+        .into_iter()
+        .find(|p| p.name == crate_name)
+        .and_then(|p| p.manifest_path.parent())
+        .map(|p| p.to_owned().into_std_path_buf())
+}
+
+/// Log the SASS entrypoints of the last build that `changed` affects.
+fn log_affected_entrypoints(config: &PacklerConfig, changed: &[PathBuf]) {
+    let Ok(metadata) = read_metadata_file(config) else {
+        return;
+    };
+    for entrypoint in sass::affected_entrypoints(&metadata.sass, changed) {
+        info!("'{}' is affected by the change", entrypoint.display());
+    }
 }
 
 pub fn path_to_watch(
@@ -86,18 +189,11 @@ pub fn path_to_watch(
     component: &Component,
 ) -> Option<PathBuf> {
     match component {
-        Component::Backend => params.backend_crate.as_ref().and_then(|crate_name| {
-            // Pretty basic by default.
-            // We watch the directory where the Cargo.toml file lies.
-            cargo_metadata()
-                .workspace_packages()
-                .into_iter()
-                .find(|p| &p.name == crate_name)
-                .and_then(|p| p.manifest_path.parent())
-                .map(|p| p.to_owned().into_std_path_buf())
-        }),
+        // Pretty basic by default.
+        // We watch the directory where the Cargo.toml file lies.
+        Component::Backend => params.backend_crate.as_deref().and_then(crate_dir),
         Component::Assets => Some(config.assets_source_dir.clone()),
-        Component::Frontend(_) => None, // FIXME
+        Component::Frontend(crate_name) => crate_dir(crate_name),
     }
 }
 
@@ -111,11 +207,49 @@ pub struct Run {
 impl Run {
     /// the `buildable_components` param lists all the possible components that
     /// can be built.
+    ///
+    /// The arguments are the ones of the process, see [`Self::from_args`].
     pub fn new(params: PacklerParams, config: PacklerConfig) -> Self {
+        Self::from_args(params, config, std::env::args_os())
+    }
+
+    /// Like [`Self::new`], with the given arguments, the first one being the
+    /// binary name. Like clap, exits on an invalid argument.
+    pub fn from_args<I, T>(params: PacklerParams, mut config: PacklerConfig, args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
         debug!("Start Manual arg parsing");
 
         let clap = build_parser();
-        let parsed = clap.get_matches();
+        let parsed = clap.get_matches_from(args);
+
+        let style = parsed
+            .get_one::<String>("log-style")
+            .and_then(LogStyle::new);
+        let verbosity = Verbosity::new(parsed.get_count("verbose"), parsed.get_flag("quiet"));
+        // Only when asked: the xtask may set up its own logger.
+        if style.is_some() || verbosity != Verbosity::Normal {
+            init_logger_with_verbosity(style.unwrap_or_default(), verbosity);
+        }
+        #[cfg(feature = "progress")]
+        if verbosity != Verbosity::Quiet {
+            progress::init_progress_bars();
+        }
+
+        if parsed.get_flag("dry-run") {
+            config.dry_run = true;
+        }
+        if parsed.get_flag("wait-for-lock") {
+            config.wait_for_lock = true;
+        }
+        if let Some(build_id) = parsed.get_one::<String>("build-id") {
+            config.build_id = Some(build_id.clone());
+        }
+        if let Some(poll_ms) = parsed.get_one::<u64>("watch-poll") {
+            config.watch_poll_ms = Some(*poll_ms);
+        }
 
         let raw_components: Vec<String> = parsed
             .get_many::<String>("components")
@@ -127,10 +261,70 @@ impl Run {
             Some(("build", args)) => {
                 // Option Watch
                 let watch = args.get_flag("watch");
-                Action::Build(BuildOpts { watch })
+                let since = args.get_one::<String>("since").cloned();
+                let profile = if args.get_flag("release") {
+                    Profile::Release
+                } else {
+                    Profile::Debug
+                };
+                Action::Build(BuildOpts {
+                    watch,
+                    since,
+                    profile,
+                })
+            }
+            Some(("clean", args)) => {
+                let all = args.get_flag("all");
+                Action::Clean(CleanOpts { all })
+            }
+            Some(("config", args)) => {
+                let format = match args.get_one::<String>("format").map(String::as_str) {
+                    Some("json") => ConfigFormat::Json,
+                    _ => ConfigFormat::Toml,
+                };
+                Action::Config(format)
+            }
+            Some(("completions", args)) => {
+                // Required by the parser.
+                let shell = args
+                    .get_one::<clap_complete::Shell>("shell")
+                    .copied()
+                    .unwrap_or(clap_complete::Shell::Bash);
+                Action::Completions(shell)
+            }
+            Some(("deploy", args)) => {
+                let watch = args.get_flag("watch");
+                let verify = args.get_one::<usize>("verify").copied();
+                let resume = args.get_flag("resume");
+                let max_upload_retries = args
+                    .get_one::<u32>("max-upload-retries")
+                    .copied()
+                    .unwrap_or_default();
+                let deploy_timeout = args
+                    .get_one::<u64>("deploy-timeout")
+                    .map(|secs| Duration::from_secs(*secs));
+                let files = args
+                    .get_many::<PathBuf>("file")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect();
+                let prune = args.get_flag("prune").then(|| PruneOpts {
+                    keep_versions: args
+                        .get_one::<usize>("keep-versions")
+                        .copied()
+                        .unwrap_or_default(),
+                    delete: args.get_flag("prune-delete"),
+                });
+                Action::Deploy(DeployOpts {
+                    watch,
+                    verify,
+                    resume,
+                    max_upload_retries,
+                    deploy_timeout,
+                    files,
+                    prune,
+                })
             }
-            Some(("clean", _args)) => Action::Clean,
-            Some(("deploy", _args)) => Action::Deploy,
             Some((cmd_name, _args)) => {
                 debug!("Action {cmd_name} is unkown");
                 Action::Unknown
@@ -141,15 +335,21 @@ impl Run {
             }
         };
 
-        let buildable_components = vec![Component::Backend, Component::Assets];
+        let mut buildable_components = vec![Component::Backend, Component::Assets];
+        buildable_components.extend(Component::frontends(&params));
 
         let components = if raw_components.is_empty() {
             buildable_components
         } else {
-            raw_components
+            let components: Result<Vec<Vec<Component>>, Error> = raw_components
                 .iter()
-                .filter_map(|name| Component::new(name).ok())
-                .collect()
+                .map(|name| Component::new(name, &params))
+                .collect();
+            match components {
+                Ok(components) => components.into_iter().flatten().collect(),
+                // Reported like the other invalid arguments.
+                Err(e) => build_parser().error(ErrorKind::InvalidValue, e).exit(),
+            }
         };
 
         Self {
@@ -160,123 +360,288 @@ impl Run {
         }
     }
 
-    /// Starth the Run. This will spawn an async runtime so the user does not
+    /// Start the Run. This will spawn an async runtime so the user does not
     /// need to provide it.
-    pub fn start(&self) {
-        tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .unwrap()
-            .block_on(async {
-                self.start_async().await;
-            });
+    ///
+    /// This panics if called from within a runtime: in async code, await
+    /// `start_async` instead (with the `async` feature).
+    pub fn start(&self) -> Result<(), PacklerError> {
+        common::block_on(self.run())
     }
 
-    /// Start the Run when you are already in an async context.
-    async fn start_async(&self) {
+    /// Start the Run on an existing runtime, blocking until it is done.
+    ///
+    /// Like [`Self::start`], this must be called from synchronous code, e.g.
+    /// the main thread or [`tokio::task::spawn_blocking`].
+    #[cfg(feature = "async")]
+    pub fn start_on(&self, handle: &tokio::runtime::Handle) -> Result<(), PacklerError> {
+        handle.block_on(self.run())
+    }
+
+    /// Start the Run when you are already in an async context. The runtime
+    /// must have the IO and time drivers enabled.
+    #[cfg(feature = "async")]
+    pub async fn start_async(&self) -> Result<(), PacklerError> {
+        self.run().await
+    }
+
+    /// In `--watch` mode, the errors of the rebuilds (or redeploys) are only
+    /// logged.
+    async fn run(&self) -> Result<(), Error> {
+        if !matches!(self.action, Action::Config(_) | Action::Completions(_)) {
+            self.config.validate()?;
+            self.params.validate()?;
+        }
+
         match &self.action {
             Action::Build(opts) => {
                 for component in &self.components {
-                    match component {
-                        Component::Assets => {
-                            let action = || async {
-                                info!("Building assets");
-                                build_assets(&self.params, &self.config).await;
-                            };
-
-                            action().await;
-
-                            if opts.watch {
-                                info!("Setting up Watcher");
-
-                                let mut latest_run = Instant::now();
-                                let debounce = Duration::from_secs(2);
-
-                                let to_watch =
-                                    path_to_watch(&self.params, &self.config, component).unwrap();
-
-                                let (tx, rx) = std::sync::mpsc::channel();
-                                let mut watcher =
-                                    RecommendedWatcher::new(tx, notify::Config::default()).unwrap();
-
-                                info!("Start to watch: {to_watch:?}, dir? {}", to_watch.is_dir());
-
-                                watcher
-                                    .watch(Path::new(&to_watch), RecursiveMode::Recursive)
-                                    .unwrap();
-
-                                while let Ok(res) = rx.recv() {
-                                    match res {
-                                        Ok(event) => {
-                                            if latest_run.elapsed() > debounce {
-                                                // The debounce here is quite gross as it is not scoped.
-                                                let changed = event
-                                                    .paths
-                                                    .iter()
-                                                    .map(|p| format!("{p:?}"))
-                                                    .collect::<Vec<String>>()
-                                                    .join(", ");
-                                                info!("Modified File '{changed}'. Reload");
-                                                action().await;
-                                                latest_run = Instant::now();
-                                            } else {
-                                                // Ignore event.
-                                                trace!("Debounce on '{event:?}'.")
-                                            }
-                                        }
-                                        Err(e) => println!("watch error: {:?}", e),
-                                    }
-                                }
-                            }
-                        }
-                        Component::Backend => {
-                            unimplemented!("Backend build is not implemented yet")
-                        }
-                        Component::Frontend(_) => {
-                            unimplemented!("Frontend build is not implemented yet")
-                        }
+                    match self.build(component, opts).await {
+                        Ok(()) => {}
+                        // The watcher retries on the next change.
+                        Err(e) if opts.watch => error!("Build failed: {e}"),
+                        Err(e) => return Err(e),
                     }
                 }
+
+                if opts.watch {
+                    // Each component rebuilds on its own changes, concurrently.
+                    let watches = self.components.iter().map(|component| {
+                        self.watch_and_run(component, || self.build(component, opts))
+                    });
+                    try_join_all(watches).await?;
+                }
             }
-            Action::Clean => {
+            Action::Clean(opts) => {
                 for component in &self.components {
                     match component {
+                        Component::Assets if opts.all => {
+                            info!("Removing the dist directory");
+                            remove_dist_dir(&self.config)?;
+                        }
                         Component::Assets => {
                             info!("Cleaning assets");
-                            clean_assets(&self.config);
+                            clean_assets(&self.config)?;
                         }
                         Component::Backend => {
-                            unimplemented!("Backend clean is not implemented yet")
+                            info!("Cleaning the backend");
+                            clean_backend(&self.params, &self.config).await?;
                         }
-                        Component::Frontend(_) => {
-                            unimplemented!("Frontend clean is not implemented yet")
+                        Component::Frontend(crate_name) => {
+                            info!("Cleaning the frontend '{crate_name}'");
+                            clean_frontend(&self.config, crate_name).await?;
                         }
                     }
                 }
             }
-            Action::Deploy => {
+            Action::Config(format) => {
+                match config::render_config(&self.params, &self.config, *format) {
+                    Ok(rendered) => println!("{rendered}"),
+                    Err(e) => return Err(Error::CannotRenderConfig(e)),
+                }
+            }
+            Action::Completions(shell) => {
+                let mut parser = build_parser();
+                let name = parser.get_name().to_owned();
+                clap_complete::generate(*shell, &mut parser, name, &mut std::io::stdout());
+            }
+            Action::Deploy(opts) => {
                 for component in &self.components {
-                    match component {
-                        Component::Assets => {
-                            info!("Deploying assets");
-                            deploy_assets(&self.params, &self.config).await;
-                        }
-                        Component::Backend => {
-                            unimplemented!("Backend deploy is not implemented yet")
-                        }
-                        Component::Frontend(_) => {
-                            unimplemented!("Frontend deploy is not implemented yet")
-                        }
+                    match self.deploy(component, opts).await {
+                        Ok(()) => {}
+                        // The watcher retries on the next change.
+                        Err(e) if opts.watch => error!("Deploy failed: {e}"),
+                        Err(e) => return Err(e),
                     }
                 }
+
+                if opts.watch {
+                    let watches = self.components.iter().map(|component| {
+                        self.watch_and_run(component, || self.deploy(component, opts))
+                    });
+                    try_join_all(watches).await?;
+                }
+            }
+            Action::Unknown => return Err(Error::NotImplemented),
+        }
+
+        Ok(())
+    }
+
+    async fn deploy(&self, component: &Component, opts: &DeployOpts) -> Result<(), Error> {
+        match component {
+            Component::Assets => self.deploy_assets(opts).await?,
+            // Nothing is uploaded: the release binary is built for the deploy
+            // (e.g., a container image) to pick it up.
+            Component::Backend => {
+                info!("Building the backend for the deploy");
+                build_backend(&self.params, &self.config, Profile::Release).await?;
+            }
+            Component::Frontend(crate_name) => {
+                info!("Building the frontend '{crate_name}' for the deploy");
+                build_frontend(&self.config, crate_name, Profile::Release).await?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "deploy")]
+    async fn deploy_assets(&self, opts: &DeployOpts) -> Result<(), Error> {
+        let _lock = BuildLock::acquire(&self.config).await?;
+
+        let report = if opts.files.is_empty() {
+            info!("Deploying assets");
+            deploy_assets(&self.params, &self.config, opts).await?
+        } else {
+            info!("Deploying {} file(s)", opts.files.len());
+            deploy_files(&self.params, &self.config, opts, &opts.files).await?
+        };
+        info!("Deployed: {report}");
+        // A partial deploy must fail the CI job.
+        report.check()?;
+
+        // Both need the bucket.
+        if self.config.dry_run {
+            info!("Dry run: nothing was deployed, nothing to verify nor prune");
+            return Ok(());
+        }
+        if let Some(sample) = opts.verify {
+            info!("Verifying deployed assets");
+            verify_assets(&self.params, &self.config, sample).await?;
+        }
+        if let Some(prune) = &opts.prune {
+            info!("Pruning stale assets");
+            prune_assets(&self.params, &self.config, prune).await?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "deploy"))]
+    async fn deploy_assets(&self, _opts: &DeployOpts) -> Result<(), Error> {
+        Err(Error::DeployNotEnabled)
+    }
+
+    async fn build(&self, component: &Component, opts: &BuildOpts) -> Result<(), Error> {
+        match component {
+            Component::Assets => {
+                let _lock = BuildLock::acquire(&self.config).await?;
+
+                info!("Building assets");
+                build_assets(
+                    &self.params,
+                    &self.config,
+                    opts.since.as_deref(),
+                    opts.profile,
+                )
+                .await?;
+                if opts.watch && self.config.watch_reload_token {
+                    write_reload_token(&self.config);
+                }
+            }
+            Component::Backend => {
+                info!("Building the backend");
+                build_backend(&self.params, &self.config, opts.profile).await?;
+            }
+            Component::Frontend(crate_name) => {
+                info!("Building the frontend '{crate_name}'");
+                build_frontend(&self.config, crate_name, opts.profile).await?;
             }
-            Action::Unknown => unimplemented!("This action is not implemented yet."),
         }
+        Ok(())
+    }
+
+    /// Call `run` (e.g., rebuild `component`) whenever the sources of
+    /// `component` change, until the watcher stops. The errors of `run` are
+    /// only logged: the next change may fix them.
+    async fn watch_and_run<F, Fut>(&self, component: &Component, run: F) -> Result<(), Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<(), Error>>,
+    {
+        let Some(to_watch) = path_to_watch(&self.params, &self.config, component) else {
+            warn!("Nothing to watch for {component:?}");
+            return Ok(());
+        };
+        self.config.check_outputs_outside(&to_watch)?;
+
+        let debounce = Duration::from_millis(self.config.watch_debounce_ms);
+        let poll = self.config.watch_poll_ms.map(Duration::from_millis);
+        let filter = ChangeFilter::new(&self.config)?;
+
+        info!("Start to watch: {to_watch:?}, dir? {}", to_watch.is_dir());
+        let run = &run;
+        watch::watch(&to_watch, debounce, poll, &filter, |changed| async move {
+            if let Component::Assets = component {
+                log_affected_entrypoints(&self.config, &changed);
+            }
+            let changed = changed
+                .iter()
+                .map(|p| format!("{p:?}"))
+                .collect::<Vec<String>>()
+                .join(", ");
+            info!("Modified File '{changed}'. Reload");
+            // Keep watching, the next change may fix it.
+            if let Err(e) = run().await {
+                error!("{component:?} failed: {e}");
+            }
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_and_components_from_the_args() {
+        let params = || PacklerParams::new(["main.scss"], ["app", "admin"], Some("server"));
+        let run = |args: &[&str]| Run::from_args(params(), PacklerConfig::default(), args);
+
+        let build = run(&["xtask", "-c", "assets", "-c", "admin", "build", "--release"]);
+        assert!(matches!(
+            build.action,
+            Action::Build(BuildOpts {
+                watch: false,
+                since: None,
+                profile: Profile::Release,
+            })
+        ));
+        assert!(matches!(
+            build.components.as_slice(),
+            [Component::Assets, Component::Frontend(name)] if name == "admin"
+        ));
+
+        let deploy = run(&[
+            "xtask",
+            "--dry-run",
+            "deploy",
+            "--watch",
+            "--prune",
+            "--keep-versions",
+            "3",
+        ]);
+        assert!(deploy.config.dry_run);
+        assert!(matches!(
+            deploy.action,
+            Action::Deploy(DeployOpts {
+                watch: true,
+                prune: Some(PruneOpts {
+                    keep_versions: 3,
+                    delete: false
+                }),
+                ..
+            })
+        ));
+        // All of them by default.
+        assert_eq!(deploy.components.len(), 4);
     }
 }
 
 pub mod cli {
-    use clap::{Arg, ArgAction, Command};
+    use clap::{value_parser, Arg, ArgAction, Command};
+    use std::path::PathBuf;
 
     pub fn build_parser() -> Command {
         Command::new("xtask")
@@ -289,18 +654,229 @@ pub mod cli {
                     .global(true)
                     .help("List the components to build. Eg., -c frontend -c backend"),
             )
+            .arg(
+                Arg::new("log-style")
+                    .long("log-style")
+                    .value_parser(["plain", "timestamped"])
+                    .global(true)
+                    .help("Install Packler's logger with this style (unless a logger is already installed)"),
+            )
+            .arg(
+                Arg::new("verbose")
+                    .short('v')
+                    .long("verbose")
+                    .action(ArgAction::Count)
+                    .global(true)
+                    .help("Install Packler's logger with the debug logs, or the trace ones with -vv"),
+            )
+            .arg(
+                Arg::new("quiet")
+                    .short('q')
+                    .long("quiet")
+                    .action(ArgAction::SetTrue)
+                    .global(true)
+                    .conflicts_with("verbose")
+                    .help("Install Packler's logger with the errors only"),
+            )
+            .arg(
+                Arg::new("dry-run")
+                    .long("dry-run")
+                    .action(ArgAction::SetTrue)
+                    .global(true)
+                    .help("Log what would be written, removed or uploaded instead of doing it"),
+            )
+            .arg(
+                Arg::new("wait-for-lock")
+                    .long("wait-for-lock")
+                    .action(ArgAction::SetTrue)
+                    .global(true)
+                    .help("Wait for another Packler build to finish instead of failing"),
+            )
+            .arg(
+                Arg::new("build-id")
+                    .long("build-id")
+                    .value_name("ID")
+                    .global(true)
+                    .help("Identify the build in the metadata file. Default: the git commit"),
+            )
+            .arg(
+                Arg::new("watch-poll")
+                    .long("watch-poll")
+                    .value_name("MS")
+                    .num_args(0..=1)
+                    .default_missing_value("1000")
+                    .value_parser(value_parser!(u64).range(1..))
+                    .global(true)
+                    .help("With --watch, scan the sources every MS milliseconds instead of relying on the events of the OS, e.g., in a container"),
+            )
             .arg_required_else_help(true)
             .subcommand_required(true)
             .subcommand(
-                Command::new("build").about("Build").arg(
-                    Arg::new("watch")
-                        .short('w')
-                        .long("watch")
+                Command::new("build")
+                    .about("Build")
+                    .arg(
+                        Arg::new("watch")
+                            .short('w')
+                            .long("watch")
+                            .action(ArgAction::SetTrue)
+                            .help("Automatically rebuild the component(s) if their source changes"),
+                    )
+                    .arg(
+                        Arg::new("since")
+                            .long("since")
+                            .value_name("GIT_REF")
+                            .help("Only rebuild the assets that changed since this git ref"),
+                    )
+                    .arg(
+                        Arg::new("release")
+                            .long("release")
+                            .action(ArgAction::SetTrue)
+                            .help("Minify the outputs, e.g. compressed CSS"),
+                    ),
+            )
+            .subcommand(
+                Command::new("clean").about("Clean ").arg(
+                    Arg::new("all")
+                        .long("all")
                         .action(ArgAction::SetTrue)
-                        .help("Automatically rebuild the component(s) if their source changes"),
+                        .help("Remove the whole dist directory"),
                 ),
             )
-            .subcommand(Command::new("clean").about("Clean "))
-            .subcommand(Command::new("deploy").about("Deploy"))
+            .subcommand(
+                Command::new("config")
+                    .about("Print the resolved configuration")
+                    .arg(
+                        Arg::new("format")
+                            .short('f')
+                            .long("format")
+                            .value_parser(["toml", "json"])
+                            .default_value("toml")
+                            .help("The output format"),
+                    ),
+            )
+            .subcommand(
+                Command::new("completions")
+                    .about("Print the completion script for a shell")
+                    .arg(
+                        Arg::new("shell")
+                            .required(true)
+                            .value_parser(value_parser!(clap_complete::Shell))
+                            .help("The shell: bash, zsh, fish, powershell or elvish"),
+                    ),
+            )
+            .subcommand(
+                Command::new("deploy")
+                    .about("Deploy")
+                    .arg(
+                        Arg::new("watch")
+                            .short('w')
+                            .long("watch")
+                            .action(ArgAction::SetTrue)
+                            .help("Automatically deploy the component(s) again if their source changes"),
+                    )
+                    .arg(
+                        Arg::new("verify")
+                            .long("verify")
+                            .value_name("SAMPLE")
+                            .num_args(0..=1)
+                            .default_missing_value("0")
+                            .value_parser(value_parser!(usize))
+                            .help("Check the uploaded assets (all of them, or a random sample of SAMPLE)"),
+                    )
+                    .arg(
+                        Arg::new("resume")
+                            .long("resume")
+                            .action(ArgAction::SetTrue)
+                            .help("Skip the assets already uploaded by an interrupted deploy"),
+                    )
+                    .arg(
+                        Arg::new("max-upload-retries")
+                            .long("max-upload-retries")
+                            .value_name("RETRIES")
+                            .value_parser(value_parser!(u32))
+                            .default_value("3")
+                            .help("How many times a failed upload is retried"),
+                    )
+                    .arg(
+                        Arg::new("deploy-timeout")
+                            .long("deploy-timeout")
+                            .value_name("SECS")
+                            .value_parser(value_parser!(u64))
+                            .help("Fail the deploy if it takes longer than this"),
+                    )
+                    .arg(
+                        Arg::new("file")
+                            .long("file")
+                            .value_name("PATH")
+                            .action(ArgAction::Append)
+                            .value_parser(value_parser!(PathBuf))
+                            .help("Only upload this built file (of the dist directory) and the metadata file"),
+                    )
+                    .arg(
+                        Arg::new("prune")
+                            .long("prune")
+                            .action(ArgAction::SetTrue)
+                            .help("List the previous versions of the assets still in the bucket"),
+                    )
+                    .arg(
+                        Arg::new("keep-versions")
+                            .long("keep-versions")
+                            .value_name("COUNT")
+                            .value_parser(value_parser!(usize))
+                            .default_value("2")
+                            .requires("prune")
+                            .help("How many previous versions of each asset are not pruned"),
+                    )
+                    .arg(
+                        Arg::new("prune-delete")
+                            .long("prune-delete")
+                            .action(ArgAction::SetTrue)
+                            .requires("prune")
+                            .help("Delete the pruned objects instead of listing them"),
+                    ),
+            )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn completions_for_a_shell() {
+            build_parser().debug_assert();
+
+            let parsed = build_parser()
+                .try_get_matches_from(["xtask", "completions", "fish"])
+                .unwrap();
+            let (_, args) = parsed.subcommand().unwrap();
+            assert_eq!(
+                args.get_one::<clap_complete::Shell>("shell"),
+                Some(&clap_complete::Shell::Fish)
+            );
+            assert!(build_parser()
+                .try_get_matches_from(["xtask", "completions", "tcsh"])
+                .is_err());
+
+            let mut script = Vec::new();
+            clap_complete::generate(
+                clap_complete::Shell::Fish,
+                &mut build_parser(),
+                "xtask",
+                &mut script,
+            );
+            assert!(String::from_utf8(script).unwrap().contains("completions"));
+        }
+
+        #[test]
+        fn unknown_components_list_the_valid_ones() {
+            let params = crate::PacklerParams::new([""; 0], ["app"], None);
+            assert_eq!(crate::Component::new("app", &params).unwrap().len(), 1);
+
+            let error = crate::Component::new("fronted", &params).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "Component 'fronted' does not exist, expected one of: backend, assets, frontend, app"
+            );
+        }
     }
 }
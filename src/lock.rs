@@ -0,0 +1,173 @@
+//! An advisory lock so two Packler processes (e.g., a watcher and a manual
+//! build) do not write in the same directories at the same time.
+//!
+//! The lock is a `flock` on a file of the intermediate directory: it is
+//! released when the process exits, even if it crashes.
+
+use crate::PacklerConfig;
+use log::{debug, info};
+use std::{fmt::Display, fs::File, path::PathBuf};
+
+pub const LOCK_FILENAME: &str = "packler.lock";
+
+/// Held for the whole build (or deploy), released on drop.
+pub struct BuildLock {
+    _file: Option<File>,
+}
+
+impl BuildLock {
+    /// Take the lock. If another process holds it, wait for it to be released
+    /// with [`PacklerConfig::wait_for_lock`], fail otherwise.
+    ///
+    /// A dry run writes nothing, not even the lock file: it takes no lock.
+    ///
+    /// The wait happens on a blocking thread, the runtime keeps running the
+    /// other tasks (e.g., the watcher).
+    pub async fn acquire(config: &PacklerConfig) -> Result<Self, Error> {
+        if config.dry_run {
+            debug!("Dry run: no lock taken");
+            return Ok(Self { _file: None });
+        }
+
+        let path = config.intermediate_dir().join(LOCK_FILENAME);
+        std::fs::create_dir_all(config.intermediate_dir()).map_err(Error::Io)?;
+        let file = File::create(&path).map_err(Error::Io)?;
+
+        if !try_lock(&file).map_err(Error::Io)? {
+            if !config.wait_for_lock {
+                return Err(Error::Locked(path));
+            }
+            info!("Another Packler build is in progress, waiting for it to finish");
+            // Both handles share the lock: it is held as long as `file` is.
+            let handle = file.try_clone().map_err(Error::Io)?;
+            tokio::task::spawn_blocking(move || lock(&handle))
+                .await
+                .map_err(|e| Error::Io(std::io::Error::other(e)))?
+                .map_err(Error::Io)?;
+        }
+
+        debug!("Lock {path:?} acquired");
+        Ok(Self { _file: Some(file) })
+    }
+}
+
+#[cfg(unix)]
+fn flock(file: &File, operation: libc::c_int) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the file descriptor is valid as long as `file` is.
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        return Ok(true);
+    }
+    let error = std::io::Error::last_os_error();
+    if error.kind() == std::io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(error)
+    }
+}
+
+/// Returns `false` if the lock is held by another process.
+#[cfg(unix)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    flock(file, libc::LOCK_EX | libc::LOCK_NB)
+}
+
+#[cfg(unix)]
+fn lock(file: &File) -> std::io::Result<()> {
+    flock(file, libc::LOCK_EX).map(|_| ())
+}
+
+// FIXME: lock on Windows as well (LockFileEx).
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> std::io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn lock(_file: &File) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Another process holds the lock.
+    Locked(PathBuf),
+    Io(std::io::Error),
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(source) => Some(source),
+            Self::Locked(_) => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Locked(path) => write!(
+                f,
+                "another Packler build is in progress (lock: '{}'), use --wait-for-lock to wait for it",
+                path.display()
+            ),
+            Self::Io(source) => write!(f, "Could not take the build lock: '{source}'"),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn a_second_build_cannot_take_the_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            target: dir.path().to_owned(),
+            ..PacklerConfig::default()
+        };
+
+        let lock = BuildLock::acquire(&config).await.unwrap();
+        assert!(matches!(
+            BuildLock::acquire(&config).await,
+            Err(Error::Locked(_))
+        ));
+
+        drop(lock);
+        assert!(BuildLock::acquire(&config).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn waiting_for_the_lock_does_not_block_the_runtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            target: dir.path().to_owned(),
+            wait_for_lock: true,
+            ..PacklerConfig::default()
+        };
+
+        let lock = BuildLock::acquire(&config).await.unwrap();
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            drop(lock);
+        });
+
+        // On this single threaded runtime, the other task only runs while
+        // `acquire` waits if the wait does not block the runtime.
+        let acquired = async {
+            BuildLock::acquire(&config).await.unwrap();
+            Instant::now()
+        };
+        let ticked = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Instant::now()
+        };
+        let (acquired, ticked) = tokio::join!(acquired, ticked);
+        assert!(ticked < acquired);
+        holder.join().unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+//! A ready to use logger, for xtasks that do not want to set up their own.
+//!
+//! Installing another logger before calling [`init_logger`] is fine: it is
+//! kept and [`init_logger`] does nothing.
+
+use once_cell::sync::Lazy;
+use std::{io::Write, time::Instant};
+
+/// When the logger was set up, used as the start of the build.
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogStyle {
+    /// The default `env_logger` output.
+    #[default]
+    Plain,
+    /// Each line is prefixed with a timestamp and the time elapsed since the
+    /// start of the build.
+    Timestamped,
+}
+
+impl LogStyle {
+    pub fn new<S: AsRef<str>>(value: S) -> Option<Self> {
+        match value.as_ref().to_lowercase().as_str() {
+            "plain" => Some(Self::Plain),
+            "timestamped" => Some(Self::Timestamped),
+            _ => None,
+        }
+    }
+}
+
+/// How much Packler logs, see the `--verbose` and `--quiet` flags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Only the errors.
+    Quiet,
+    #[default]
+    Normal,
+    /// The debug logs of Packler.
+    Verbose,
+    /// The trace logs of Packler.
+    Trace,
+}
+
+impl Verbosity {
+    /// From the number of `-v` and the `-q` flag.
+    pub fn new(verbose: u8, quiet: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Self::Quiet,
+            (false, 0) => Self::Normal,
+            (false, 1) => Self::Verbose,
+            (false, _) => Self::Trace,
+        }
+    }
+
+    /// The default `env_logger` filter. The other crates (e.g., the AWS SDK)
+    /// stay at the `info` level.
+    fn filter(&self) -> &'static str {
+        match self {
+            Self::Quiet => "error",
+            Self::Normal => "info",
+            Self::Verbose => "info,packler=debug",
+            Self::Trace => "info,packler=trace",
+        }
+    }
+}
+
+/// Install an `env_logger` logger with the given style, defaulting to the
+/// `info` level (`RUST_LOG` is still honored).
+///
+/// Does nothing if a logger is already installed.
+pub fn init_logger(style: LogStyle) {
+    init_logger_with_verbosity(style, Verbosity::Normal);
+}
+
+/// Like [`init_logger`], with the level of `verbosity`. `RUST_LOG` still takes
+/// precedence.
+pub fn init_logger_with_verbosity(style: LogStyle, verbosity: Verbosity) {
+    Lazy::force(&START);
+
+    let mut builder = env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or(verbosity.filter()),
+    );
+
+    if style == LogStyle::Timestamped {
+        builder.format(|buf, record| {
+            let elapsed = START.elapsed().as_secs_f64();
+            writeln!(
+                buf,
+                "{} +{elapsed:>8.3}s {:<5} {}: {}",
+                buf.timestamp_millis(),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        });
+    }
+
+    if builder.try_init().is_err() {
+        log::debug!("A logger is already installed, keep it");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_wins_over_verbose() {
+        assert_eq!(Verbosity::new(0, false), Verbosity::Normal);
+        assert_eq!(Verbosity::new(1, false), Verbosity::Verbose);
+        assert_eq!(Verbosity::new(3, false), Verbosity::Trace);
+        assert_eq!(Verbosity::new(2, true), Verbosity::Quiet);
+    }
+}
@@ -1,65 +1,447 @@
-use super::AssetsOutput;
-use crate::PacklerConfig;
+use super::{
+    compress::Encoding,
+    ledger::{ledger_path, UploadLedger},
+    AssetMetadata, AssetsOutput, Error,
+};
+use crate::progress::{self, Phase};
+use crate::{common, PacklerConfig};
 use aws_config::SdkConfig;
 use aws_sdk_s3::{
     config::Region,
+    error::SdkError,
     primitives::ByteStream,
-    types::{CorsConfiguration, CorsRule, ObjectCannedAcl},
+    types::{
+        CompletedMultipartUpload, CompletedPart, CorsConfiguration, CorsRule, ObjectCannedAcl,
+        StorageClass,
+    },
     Client,
 };
-use log::{debug, warn};
+use aws_smithy_http::byte_stream::Length;
+use futures_util::{stream, Stream, StreamExt};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::RandomState, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    hash::BuildHasher,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
-#[derive(Debug)]
+/// The `log` target of this pipeline, see [`crate::pipelines`].
+pub const LOG_TARGET: &str = "packler::bucket";
+
+/// Files bigger than this are uploaded in several parts.
+pub const DEFAULT_UPLOAD_PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// S3 refuses parts smaller than 5MiB (except for the last one).
+pub const MIN_UPLOAD_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// How many files are uploaded at the same time.
+pub const DEFAULT_UPLOAD_CONCURRENCY: usize = 8;
+
+/// The content of a hashed asset never changes: it can be cached forever.
+pub const DEFAULT_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// The unhashed assets and the metadata file may change on every deploy.
+pub const MUTABLE_CACHE_CONTROL: &str = "no-cache";
+
+/// The `Content-Type` of the objects whose type cannot be guessed from their
+/// extension.
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The wait before the first retry of a failed upload, doubled after each
+/// attempt up to [`MAX_UPLOAD_RETRY_DELAY`].
+const UPLOAD_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_UPLOAD_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AssetsBucketParams {
     pub bucket_name: String,
 
     /// Eg., "fr-par"
     pub bucket_region: String,
 
-    /// Eg., "https://s3.fr-par.scw.cloud"
-    pub bucket_endpoint_url: String,
+    /// Only needed for S3 compatible providers, eg.,
+    /// "https://s3.fr-par.scw.cloud". The AWS S3 endpoint of the region is
+    /// used otherwise.
+    #[serde(default, serialize_with = "serialize_endpoint_url")]
+    pub bucket_endpoint_url: Option<String>,
+
+    /// Upload the objects under this prefix, e.g., `myapp/v3` to serve several
+    /// apps from the same bucket. The prune only lists the objects under it.
+    /// The CORS rules still apply to the whole bucket.
+    ///
+    /// Default: the root of the bucket
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+
+    /// The CORS rules of the bucket, one per group of origins. Eg.,
+    /// `vec![CorsRuleSpec::new(["https://example.com"])]`
+    #[serde(default)]
+    pub cors_rules: Vec<CorsRuleSpec>,
+
+    /// Files bigger than this are sent with a multipart upload, in parts of
+    /// this size. Each part is streamed from the disk so the memory usage
+    /// does not depend on the size of the file.
+    ///
+    /// Default: [`DEFAULT_UPLOAD_PART_SIZE`], never less than
+    /// [`MIN_UPLOAD_PART_SIZE`].
+    #[serde(default)]
+    pub upload_part_size: Option<u64>,
 
-    /// Allowed origin will be use to set the CORS rules
+    /// How many files are uploaded at the same time.
+    ///
+    /// Default: [`DEFAULT_UPLOAD_CONCURRENCY`], never less than 1.
+    #[serde(default)]
+    pub upload_concurrency: Option<usize>,
+
+    /// The `Cache-Control` header of the hashed assets. The others (and the
+    /// metadata file) get [`MUTABLE_CACHE_CONTROL`].
+    ///
+    /// Default: [`DEFAULT_CACHE_CONTROL`]
+    #[serde(default)]
+    pub cache_control: Option<String>,
+
+    /// Assets whose `processed_relative_path` matches one of these globs are
+    /// not uploaded. They are still built and listed in the metadata file.
+    /// Eg., `["**/*.map"]`
+    #[serde(default)]
+    pub deploy_exclude_globs: Vec<String>,
+
+    /// Upload the metadata file as well, after all the assets. A deploy that
+    /// cannot upload it fails.
+    #[serde(default)]
+    pub upload_metadata: bool,
+
+    /// Do not upload the hashed assets already in the bucket: their key
+    /// changes with their content. The directories of the assets are listed
+    /// before the upload. The unhashed assets are always uploaded.
+    ///
+    /// Default: `false`, everything is uploaded
+    #[serde(default)]
+    pub skip_existing: bool,
+
+    /// The storage class of the uploaded objects, eg.,
+    /// `Some(StorageClass::StandardIa)` for a bucket only read by a CDN.
+    ///
+    /// Default: the default of the bucket (`STANDARD` on AWS)
+    #[serde(
+        default,
+        serialize_with = "serialize_storage_class",
+        deserialize_with = "deserialize_storage_class"
+    )]
+    pub storage_class: Option<StorageClass>,
+
+    /// The `Content-Type` of the uploaded objects, by extension (without the
+    /// dot), e.g., `"webmanifest"` → `"application/manifest+json"`. The other
+    /// types are guessed from the extension, with
+    /// [`DEFAULT_CONTENT_TYPE`] as a fallback.
+    #[serde(default)]
+    pub content_type_overrides: HashMap<String, String>,
+}
+
+impl AssetsBucketParams {
+    /// The bucket `bucket_name` of `bucket_region`, with the defaults of the
+    /// other params. Set them with the struct update syntax, e.g.,
+    /// `AssetsBucketParams { upload_metadata: true, ..AssetsBucketParams::new("assets", "fr-par") }`.
+    pub fn new<S: Into<String>>(bucket_name: S, bucket_region: S) -> Self {
+        Self {
+            bucket_name: bucket_name.into(),
+            bucket_region: bucket_region.into(),
+            bucket_endpoint_url: None,
+            key_prefix: None,
+            cors_rules: Vec::new(),
+            upload_part_size: None,
+            upload_concurrency: None,
+            cache_control: None,
+            deploy_exclude_globs: Vec::new(),
+            upload_metadata: false,
+            skip_existing: false,
+            storage_class: None,
+            content_type_overrides: HashMap::new(),
+        }
+    }
+
+    /// Check the parameters before deploying.
+    pub fn validate(&self) -> Result<(), Error> {
+        match &self.storage_class {
+            Some(storage_class @ StorageClass::Unknown(_)) => Err(Error::UnknownStorageClass(
+                storage_class.as_str().to_owned(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Without its credentials, see [`crate::config::render_config`].
+fn serialize_endpoint_url<S: serde::Serializer>(
+    endpoint_url: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match endpoint_url {
+        Some(endpoint_url) => serializer.serialize_some(&crate::config::redact_url(endpoint_url)),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_storage_class<S: serde::Serializer>(
+    storage_class: &Option<StorageClass>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match storage_class {
+        Some(storage_class) => serializer.serialize_some(storage_class.as_str()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// An unknown storage class is kept, and refused by
+/// [`AssetsBucketParams::validate`].
+fn deserialize_storage_class<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<StorageClass>, D::Error> {
+    let storage_class = Option::<String>::deserialize(deserializer)?;
+    Ok(storage_class.as_deref().map(StorageClass::from))
+}
+
+/// One CORS rule of the bucket. In a configuration file, the missing fields
+/// are the ones of [`CorsRuleSpec::new`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsRuleSpec {
     pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub expose_headers: Vec<String>,
+    pub max_age_seconds: i32,
+}
+
+impl Default for CorsRuleSpec {
+    fn default() -> Self {
+        Self::new::<String, _>([])
+    }
+}
+
+impl CorsRuleSpec {
+    /// Allow `GET` and `HEAD` requests, with any header, from `origins`.
+    pub fn new<S, O>(origins: O) -> Self
+    where
+        S: Into<String>,
+        O: IntoIterator<Item = S>,
+    {
+        Self {
+            allowed_origins: origins.into_iter().map(Into::into).collect(),
+            allowed_methods: vec!["GET".to_owned(), "HEAD".to_owned()],
+            allowed_headers: vec!["*".to_owned()],
+            expose_headers: vec!["Etag".to_owned()],
+            max_age_seconds: 360,
+        }
+    }
+
+    fn build(&self) -> CorsRule {
+        CorsRule::builder()
+            .set_allowed_origins(Some(self.allowed_origins.clone()))
+            .set_allowed_methods(Some(self.allowed_methods.clone()))
+            .set_allowed_headers(Some(self.allowed_headers.clone()))
+            .set_expose_headers(Some(self.expose_headers.clone()))
+            .max_age_seconds(self.max_age_seconds)
+            .build()
+    }
 }
 
 pub struct AssetBucket {
     client: Client,
     bucket_name: String,
+    /// Empty, or ends with a `/`.
+    key_prefix: String,
     cors_config: CorsConfiguration,
+    upload_part_size: u64,
+    upload_concurrency: usize,
+    cache_control: String,
+    deploy_exclude: GlobSet,
+    max_upload_retries: u32,
+    dry_run: bool,
+    skip_existing: bool,
+    uploaded: AtomicUsize,
+    storage_class: Option<StorageClass>,
+    content_type_overrides: HashMap<String, String>,
 }
 
 impl AssetBucket {
     /// This will fetch the credentials from the environment.
     pub async fn new(config: &AssetsBucketParams) -> Self {
         let aws_config = aws_config::load_from_env().await;
-        Self::with_aws_config(&aws_config, &config)
+        Self::with_aws_config(&aws_config, config)
     }
 
     pub fn with_aws_config(aws_config: &SdkConfig, config: &AssetsBucketParams) -> Self {
-        let s3_config = aws_sdk_s3::config::Builder::from(aws_config)
-            .region(Region::new(config.bucket_region.clone()))
-            .endpoint_url(&config.bucket_endpoint_url)
-            .build();
+        let mut s3_config = aws_sdk_s3::config::Builder::from(aws_config)
+            .region(Region::new(config.bucket_region.clone()));
+        if let Some(endpoint_url) = &config.bucket_endpoint_url {
+            s3_config = s3_config.endpoint_url(endpoint_url);
+        }
+        let s3_config = s3_config.build();
         Self {
             client: aws_sdk_s3::Client::from_conf(s3_config),
             bucket_name: config.bucket_name.clone(),
+            key_prefix: match config.key_prefix.as_deref().map(|p| p.replace('\\', "/")) {
+                Some(prefix) if !prefix.trim_matches('/').is_empty() => {
+                    format!("{}/", prefix.trim_matches('/'))
+                }
+                _ => String::new(),
+            },
             cors_config: CorsConfiguration::builder()
-                .cors_rules(
-                    CorsRule::builder()
-                        .set_allowed_origins(Some(config.allowed_origins.clone()))
-                        .allowed_headers("*")
-                        .allowed_methods("GET")
-                        .allowed_methods("HEAD")
-                        .expose_headers("Etag")
-                        .max_age_seconds(360)
-                        .build(),
-                )
+                .set_cors_rules(Some(
+                    config.cors_rules.iter().map(CorsRuleSpec::build).collect(),
+                ))
                 .build(),
+            upload_part_size: config
+                .upload_part_size
+                .unwrap_or(DEFAULT_UPLOAD_PART_SIZE)
+                .max(MIN_UPLOAD_PART_SIZE),
+            upload_concurrency: config
+                .upload_concurrency
+                .unwrap_or(DEFAULT_UPLOAD_CONCURRENCY)
+                .max(1),
+            cache_control: config
+                .cache_control
+                .clone()
+                .unwrap_or_else(|| DEFAULT_CACHE_CONTROL.to_owned()),
+            deploy_exclude: build_glob_set(&config.deploy_exclude_globs),
+            max_upload_retries: 0,
+            dry_run: false,
+            skip_existing: config.skip_existing,
+            uploaded: AtomicUsize::new(0),
+            storage_class: config.storage_class.clone(),
+            content_type_overrides: config
+                .content_type_overrides
+                .iter()
+                .map(|(extension, content_type)| {
+                    let extension = extension.trim_start_matches('.').to_lowercase();
+                    (extension, content_type.clone())
+                })
+                .collect(),
         }
     }
 
+    /// Retry a failed upload up to `retries` times, waiting longer after each
+    /// attempt. Default: no retries.
+    pub fn with_max_upload_retries(mut self, retries: u32) -> Self {
+        self.max_upload_retries = retries;
+        self
+    }
+
+    /// Log the requests that would change the bucket instead of sending them.
+    /// Default: `false`.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// The number of objects uploaded so far.
+    pub fn uploaded_count(&self) -> usize {
+        self.uploaded.load(Ordering::Relaxed)
+    }
+
+    /// The keys of the hashed `paths` (relative to the dist directory) already
+    /// in the bucket. Only the directories of the paths are listed.
+    async fn existing_objects(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
+        let keys: HashSet<String> = paths
+            .iter()
+            .filter(|path| is_hashed(path))
+            .map(|path| self.object_key(path))
+            .collect();
+        // An object at the root is listed on its own.
+        let prefixes: BTreeSet<&str> = keys
+            .iter()
+            .map(|key| key.rsplit_once('/').map_or(key.as_str(), |(dir, _)| dir))
+            .collect();
+
+        let mut existing = HashSet::new();
+        for prefix in prefixes {
+            let prefix = if keys.contains(prefix) {
+                prefix.to_owned()
+            } else {
+                format!("{prefix}/")
+            };
+            for (key, _) in self.list_objects(&prefix).await? {
+                if keys.contains(&key) {
+                    existing.insert(key);
+                }
+            }
+        }
+        Ok(existing)
+    }
+
+    /// The keys of the objects starting with `prefix`, with the date of their
+    /// last modification (in seconds).
+    async fn list_objects(
+        &self,
+        prefix: &str,
+    ) -> Result<Vec<(String, i64)>, Box<dyn std::error::Error>> {
+        let mut objects = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let listed = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket_name)
+                .prefix(prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await?;
+            objects.extend(
+                listed
+                    .contents()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|object| {
+                        let last_modified = object.last_modified().map_or(0, |date| date.secs());
+                        Some((object.key()?.to_owned(), last_modified))
+                    }),
+            );
+
+            continuation_token = listed.next_continuation_token().map(str::to_owned);
+            if !listed.is_truncated() || continuation_token.is_none() {
+                return Ok(objects);
+            }
+        }
+    }
+
+    /// The key of the object of `path`, relative to the dist directory.
+    fn object_key(&self, path: &Path) -> String {
+        format!("{}{}", self.key_prefix, to_object_key(path))
+    }
+
+    /// Set the CORS rules of the bucket, see
+    /// [`AssetsBucketParams::cors_rules`]. Without rules, the CORS config of
+    /// the bucket is left as is: it may be managed outside Packler.
     pub async fn send_cors(&self) {
+        if self.cors_config.cors_rules().is_none_or(<[_]>::is_empty) {
+            debug!(
+                target: LOG_TARGET,
+                "No CORS rules, the CORS config of '{}' is left as is", self.bucket_name
+            );
+            return;
+        }
+        info!(
+            target: LOG_TARGET,
+            "setting CORS config on assets bucket '{}'", self.bucket_name
+        );
+        if self.dry_run {
+            info!(
+                target: LOG_TARGET,
+                "DRY RUN: would set the CORS rules of '{}'", self.bucket_name
+            );
+            return;
+        }
+
         let res = self
             .client
             .put_bucket_cors()
@@ -70,7 +452,7 @@ impl AssetBucket {
 
         match res {
             Ok(_) => {}
-            Err(e) => warn!("could not set CORS: {e}"),
+            Err(e) => warn!(target: LOG_TARGET, "could not set CORS: {e}"),
         }
     }
 
@@ -84,40 +466,1013 @@ impl AssetBucket {
     /// the same time (e.g., you have a rollout deploy and different versions of the
     /// app might be running at the same time).
     ///
-    pub async fn send_assets(&self, cfg: &PacklerConfig, metadata: &AssetsOutput) {
-        for item in metadata.iter() {
-            // We always reupload everything.
-            let src = cfg.dist_dir.join(&item.processed_relative_path);
-            let object_name = item.processed_relative_path.to_string_lossy();
-            let mime_type = mime_guess::from_path(&src)
-                .first_raw()
-                .expect("could not get content type");
+    /// The uploaded assets are recorded in an [`UploadLedger`], cleared once
+    /// everything is uploaded. With `resume`, the assets recorded by a previous
+    /// (interrupted) run are not uploaded again.
+    ///
+    /// Up to [`AssetsBucketParams::upload_concurrency`] assets are uploaded at
+    /// the same time.
+    ///
+    /// The assets that could not be uploaded, once the others are, are listed
+    /// in the [`DeployReport::failed`].
+    pub async fn send_assets(
+        &self,
+        cfg: &PacklerConfig,
+        metadata: &AssetsOutput,
+        resume: bool,
+    ) -> DeployReport {
+        // A dry run writes nothing, not even the ledger.
+        let ledger = if self.dry_run {
+            Ok(None)
+        } else {
+            UploadLedger::open(ledger_path(cfg, metadata, &self.bucket_name), resume).map(Some)
+        };
+        let mut ledger = match ledger {
+            Ok(ledger) => ledger,
+            Err(e) => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Could not open the upload ledger, deploy cannot be resumed: {e}"
+                );
+                None
+            }
+        };
+        let mut report = DeployReport::default();
+        let mut to_upload = Vec::new();
 
-            debug!(
-                "Uploading '{}' to: '{}' (content-type: '{}'))",
-                src.display(),
-                object_name,
-                mime_type
+        // With their pre-compressed variants.
+        for path in metadata.iter().flat_map(AssetMetadata::outputs) {
+            if self.deploy_exclude.is_match(path) {
+                debug!(
+                    target: LOG_TARGET,
+                    "Skipping '{}': excluded from deploy",
+                    path.display()
+                );
+                report.skipped += 1;
+                continue;
+            }
+
+            // We reupload everything, unless `skip_existing`.
+            let object_name = self.object_key(path);
+            if ledger.as_ref().is_some_and(|l| l.contains(&object_name)) {
+                debug!(target: LOG_TARGET, "Skipping '{object_name}': already uploaded");
+                report.skipped += 1;
+                continue;
+            }
+            to_upload.push(path.to_owned());
+        }
+
+        // A dry run makes no request to the bucket.
+        if self.skip_existing && !self.dry_run {
+            match self.existing_objects(&to_upload).await {
+                Ok(existing) => to_upload.retain(|path| {
+                    let object_name = self.object_key(path);
+                    if !existing.contains(&object_name) {
+                        return true;
+                    }
+                    debug!(target: LOG_TARGET, "Skipping '{object_name}': already in the bucket");
+                    report.skipped += 1;
+                    false
+                }),
+                Err(e) => warn!(
+                    target: LOG_TARGET,
+                    "Could not list the objects of '{}', uploading everything: {e}",
+                    self.bucket_name
+                ),
+            }
+        }
+
+        let mut uploads = self.send_concurrently(cfg, to_upload);
+        while let Some((path, upload)) = uploads.next().await {
+            let object_name = self.object_key(&path);
+            match upload {
+                Ok(bytes) => {
+                    report.uploaded += 1;
+                    report.bytes += bytes;
+                }
+                Err(e) => {
+                    report.failed.push((object_name, e.to_string()));
+                    continue;
+                }
+            }
+
+            if let Some(Err(e)) = ledger.as_mut().map(|l| l.record(&object_name)) {
+                warn!(
+                    target: LOG_TARGET,
+                    "Could not record '{object_name}' in the upload ledger: {e}"
+                );
+            }
+        }
+
+        if !report.failed.is_empty() {
+            report.failed.sort();
+            return report;
+        }
+
+        if let Some(Err(e)) = ledger.map(UploadLedger::clear) {
+            warn!(target: LOG_TARGET, "Could not clear the upload ledger: {e}");
+        }
+        report
+    }
+
+    /// Upload the files at `paths`, relative to [`PacklerConfig::dist_dir`],
+    /// like [`Self::send_assets`] but without the ledger.
+    ///
+    /// The files that could not be uploaded, once the others are, are listed
+    /// in the [`DeployReport::failed`].
+    pub async fn send_files(&self, cfg: &PacklerConfig, paths: &[PathBuf]) -> DeployReport {
+        let mut report = DeployReport::default();
+        let mut uploads = self.send_concurrently(cfg, paths.to_vec());
+        while let Some((path, upload)) = uploads.next().await {
+            match upload {
+                Ok(bytes) => {
+                    report.uploaded += 1;
+                    report.bytes += bytes;
+                }
+                Err(e) => report.failed.push((self.object_key(&path), e.to_string())),
+            }
+        }
+        report.failed.sort();
+        report
+    }
+
+    /// Upload the files at `paths`, relative to [`PacklerConfig::dist_dir`],
+    /// [`Self::upload_concurrency`] at a time. A file is only opened once its
+    /// upload starts.
+    ///
+    /// Yields each path with the result of its upload (the bytes uploaded), as
+    /// they complete.
+    fn send_concurrently<'a>(
+        &'a self,
+        cfg: &'a PacklerConfig,
+        paths: Vec<PathBuf>,
+    ) -> impl Stream<Item = (PathBuf, Result<u64, Box<dyn std::error::Error>>)> + 'a {
+        progress::start(Phase::Upload, paths.len());
+        stream::iter(paths)
+            .map(move |path| async move {
+                let src = cfg.dist_dir.join(&path);
+                let upload = self.send_file(&src, &self.object_key(&path)).await;
+                if let Err(err) = &upload {
+                    warn!(target: LOG_TARGET, "Could not upload {}: {err:?}", src.display());
+                }
+                progress::advance(Phase::Upload);
+                (path, upload)
+            })
+            .buffer_unordered(self.upload_concurrency)
+    }
+
+    /// Upload `src` as `object_name`, retrying up to
+    /// [`Self::with_max_upload_retries`] times. Returns the bytes uploaded,
+    /// none in a dry run.
+    async fn send_file(
+        &self,
+        src: &Path,
+        object_name: &str,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let headers = self.object_headers(src);
+
+        debug!(
+            target: LOG_TARGET,
+            "Uploading '{}' to: '{}' (content-type: '{}', cache-control: '{}'))",
+            src.display(),
+            object_name,
+            headers.content_type,
+            headers.cache_control
+        );
+
+        let size = std::fs::metadata(src).map(|metadata| metadata.len());
+
+        if self.dry_run {
+            // The assets are not written by a dry run build.
+            let size = size.map_or("not built".to_owned(), |size| format!("{size} bytes"));
+            info!(
+                target: LOG_TARGET,
+                "DRY RUN: would upload '{object_name}' ({size}, content-type: '{}', \
+                 cache-control: '{}') to '{}'",
+                headers.content_type,
+                headers.cache_control,
+                self.bucket_name
+            );
+            return Ok(0);
+        }
+        // E.g., a stale metadata file: reported as a failed upload.
+        let size = size.map_err(|e| format!("Could not read '{}': {e}", src.display()))?;
+
+        let mut attempt = 0;
+        loop {
+            let upload = if size > self.upload_part_size {
+                self.send_multipart(src, object_name, &headers, size).await
+            } else {
+                self.send_single(src, object_name, &headers).await
+            };
+
+            match upload {
+                Err(err) if attempt < self.max_upload_retries => {
+                    attempt += 1;
+                    let backoff = retry_delay(attempt);
+                    warn!(
+                        target: LOG_TARGET,
+                        "Could not upload {} ({err}), retrying in {backoff:?} ({attempt}/{})",
+                        src.display(),
+                        self.max_upload_retries
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                Ok(()) => {
+                    debug!(target: LOG_TARGET, "Asset Uploaded");
+                    self.uploaded.fetch_add(1, Ordering::Relaxed);
+                    return Ok(size);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Upload the metadata file (see [`PacklerConfig::metadata_file`]).
+    pub async fn send_metadata(
+        &self,
+        cfg: &PacklerConfig,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        debug!(
+            target: LOG_TARGET,
+            "Uploading metadata file to: '{}'", cfg.metadata_filename
+        );
+        if self.dry_run {
+            info!(
+                target: LOG_TARGET,
+                "DRY RUN: would upload the metadata file '{}' to '{}'",
+                cfg.metadata_filename,
+                self.bucket_name
             );
+            return Ok(());
+        }
 
-            let stream = ByteStream::from_path(&src)
-                .await
-                .expect("Could not open file to upload");
+        let headers = ObjectHeaders {
+            content_type: "application/json",
+            content_encoding: None,
+            cache_control: MUTABLE_CACHE_CONTROL,
+        };
+        let object_name = self.object_key(Path::new(&cfg.metadata_filename));
+        self.send_single(&cfg.metadata_file(), &object_name, &headers)
+            .await
+    }
+
+    fn object_headers(&self, src: &Path) -> ObjectHeaders<'_> {
+        let content_encoding = Encoding::from_path(src).map(|encoding| encoding.content_encoding());
+        // The type of the decoded content: `app.css.gz` is CSS.
+        let decoded = match content_encoding {
+            Some(_) => src.with_extension(""),
+            None => src.to_owned(),
+        };
+        let content_type = self.content_type(&decoded);
+        let cache_control = match super::unhashed_path(&decoded) {
+            Some(_) => self.cache_control.as_str(),
+            None => MUTABLE_CACHE_CONTROL,
+        };
 
-            let upload = self
+        ObjectHeaders {
+            content_type,
+            content_encoding,
+            cache_control,
+        }
+    }
+
+    /// The `Content-Type` of `path`: overridden by its extension, or guessed.
+    fn content_type(&self, path: &Path) -> &str {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        extension
+            .and_then(|extension| self.content_type_overrides.get(&extension))
+            .map(String::as_str)
+            .or_else(|| mime_guess::from_path(path).first_raw())
+            .unwrap_or(DEFAULT_CONTENT_TYPE)
+    }
+
+    async fn send_single(
+        &self,
+        src: &Path,
+        object_name: &str,
+        headers: &ObjectHeaders<'_>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let stream = ByteStream::from_path(src).await?;
+
+        self.client
+            .put_object()
+            .key(object_name)
+            .bucket(&self.bucket_name)
+            .acl(ObjectCannedAcl::PublicRead)
+            .content_type(headers.content_type)
+            .set_content_encoding(headers.content_encoding.map(str::to_owned))
+            .cache_control(headers.cache_control)
+            .set_storage_class(self.storage_class.clone())
+            .body(stream)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Upload `src` in parts of [`Self::upload_part_size`]. Only one part is
+    /// read from the disk at a time.
+    async fn send_multipart(
+        &self,
+        src: &Path,
+        object_name: &str,
+        headers: &ObjectHeaders<'_>,
+        size: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let multipart = self
+            .client
+            .create_multipart_upload()
+            .key(object_name)
+            .bucket(&self.bucket_name)
+            .acl(ObjectCannedAcl::PublicRead)
+            .content_type(headers.content_type)
+            .set_content_encoding(headers.content_encoding.map(str::to_owned))
+            .cache_control(headers.cache_control)
+            .set_storage_class(self.storage_class.clone())
+            .send()
+            .await?;
+        let upload_id = multipart
+            .upload_id()
+            .ok_or("no upload id for the multipart upload")?;
+
+        debug!(
+            target: LOG_TARGET,
+            "Multipart upload of '{object_name}' ({size} bytes) started: {upload_id}"
+        );
+
+        let mut completed_parts = Vec::new();
+        for (part_number, offset, length) in part_ranges(size, self.upload_part_size) {
+            let part = async {
+                let stream = part_stream(src, offset, length).await?;
+                let uploaded = self
+                    .client
+                    .upload_part()
+                    .key(object_name)
+                    .bucket(&self.bucket_name)
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .body(stream)
+                    .send()
+                    .await?;
+                Ok::<_, Box<dyn std::error::Error>>(uploaded)
+            };
+
+            match part.await {
+                Ok(uploaded) => completed_parts.push(
+                    CompletedPart::builder()
+                        .set_e_tag(uploaded.e_tag().map(str::to_owned))
+                        .part_number(part_number)
+                        .build(),
+                ),
+                Err(err) => {
+                    // Do not leave the parts lying around in the bucket.
+                    let _ = self
+                        .client
+                        .abort_multipart_upload()
+                        .key(object_name)
+                        .bucket(&self.bucket_name)
+                        .upload_id(upload_id)
+                        .send()
+                        .await;
+                    return Err(err);
+                }
+            }
+        }
+
+        self.client
+            .complete_multipart_upload()
+            .key(object_name)
+            .bucket(&self.bucket_name)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(completed_parts))
+                    .build(),
+            )
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// HEAD the uploaded assets listed in `metadata`, with their variants and
+    /// source maps, and report the ones that are not reachable or that differ
+    /// from the local files.
+    ///
+    /// Only a random sample of `sample` files is checked, or all of them if
+    /// `sample` is `0`.
+    pub async fn verify(
+        &self,
+        cfg: &PacklerConfig,
+        metadata: &AssetsOutput,
+        sample: usize,
+    ) -> Vec<VerifyMismatch> {
+        let mut paths: Vec<&Path> = metadata
+            .iter()
+            .flat_map(AssetMetadata::outputs)
+            .filter(|path| !self.deploy_exclude.is_match(path))
+            .collect();
+
+        if sample > 0 && sample < paths.len() {
+            // Good enough randomness to pick a sample: a randomly seeded hash.
+            let random = RandomState::new();
+            paths.sort_by_cached_key(|path| random.hash_one(path));
+            paths.truncate(sample);
+        }
+
+        let mut mismatches = Vec::new();
+        for path in paths {
+            let src = cfg.dist_dir.join(path);
+            let key = self.object_key(path);
+
+            debug!(target: LOG_TARGET, "Verifying '{key}'");
+
+            let head = self
                 .client
-                .put_object()
-                .key(object_name)
+                .head_object()
+                .key(&key)
+                .bucket(&self.bucket_name)
+                .send()
+                .await;
+
+            let head = match head {
+                Ok(head) => Some(head),
+                Err(SdkError::ServiceError(e)) if e.err().is_not_found() => None,
+                Err(err) => {
+                    mismatches.push(VerifyMismatch::Unreachable {
+                        key,
+                        error: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let expected_size = std::fs::metadata(&src).map(|m| m.len()).unwrap_or(0);
+            mismatches.extend(compare_object(
+                &key,
+                expected_size,
+                self.object_headers(&src).content_type,
+                head.as_ref()
+                    .map(|head| (head.content_length(), head.content_type())),
+            ));
+        }
+
+        mismatches
+    }
+
+    /// The stale objects of the bucket: the previous versions of the hashed
+    /// assets, older than the `keep_versions` most recent ones. The objects
+    /// of the current `metadata` are never stale, nor the unhashed ones.
+    ///
+    /// Only the directories of the assets are listed (e.g., `css/` and
+    /// `images/`). The stale objects are deleted with `delete`, unless in a
+    /// dry run: they are only listed otherwise.
+    pub async fn prune_assets(
+        &self,
+        cfg: &PacklerConfig,
+        metadata: &AssetsOutput,
+        keep_versions: usize,
+        delete: bool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let current: HashSet<String> = metadata
+            .iter()
+            .flat_map(AssetMetadata::outputs)
+            .map(|path| self.object_key(path))
+            .collect();
+        let prefixes: BTreeSet<String> = metadata
+            .iter()
+            .filter(|asset| asset.processed_relative_path.parent() != Some(Path::new("")))
+            .filter_map(|asset| asset.processed_relative_path.components().next())
+            .map(|dir| format!("{}/", self.object_key(Path::new(dir.as_os_str()))))
+            .collect();
+
+        let mut objects = Vec::new();
+        for prefix in prefixes {
+            objects.extend(self.list_objects(&prefix).await?);
+        }
+
+        let stale = stale_objects(&current, objects, keep_versions);
+        for key in &stale {
+            if !delete || cfg.dry_run {
+                info!(target: LOG_TARGET, "DRY RUN: would delete '{key}'");
+                continue;
+            }
+
+            debug!(target: LOG_TARGET, "Deleting '{key}'");
+            self.client
+                .delete_object()
                 .bucket(&self.bucket_name)
-                .acl(ObjectCannedAcl::PublicRead)
-                .content_type(mime_type)
-                .body(stream)
-                .send();
-
-            match upload.await {
-                Ok(_resp) => debug!("Asset Uploaded"),
-                Err(err) => warn!("Could not upload {}: {err:?}", src.display()),
+                .key(key)
+                .send()
+                .await?;
+        }
+        Ok(stale)
+    }
+}
+
+/// The keys of `objects` (with their last modification) that are neither
+/// `current` nor one of the `keep_versions` most recent versions of their
+/// asset. The versions of an asset only differ by their hash.
+fn stale_objects(
+    current: &HashSet<String>,
+    objects: Vec<(String, i64)>,
+    keep_versions: usize,
+) -> Vec<String> {
+    let mut versions: HashMap<PathBuf, Vec<(String, i64)>> = HashMap::new();
+    for (key, last_modified) in objects {
+        if current.contains(&key) {
+            continue;
+        }
+        if let Some(asset) = unhashed_key(&key) {
+            versions
+                .entry(asset)
+                .or_default()
+                .push((key, last_modified));
+        }
+    }
+
+    let mut stale: Vec<String> = versions
+        .into_values()
+        .flat_map(|mut versions| {
+            versions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            versions.into_iter().skip(keep_versions).map(|(key, _)| key)
+        })
+        .collect();
+    stale.sort();
+    stale
+}
+
+/// The object of `key` without its hash, e.g. `css/app.css.gz` for
+/// `css/app-<hash>.css.gz`. The pre-compressed variants and the source maps
+/// are versioned like their asset, but apart from it.
+fn unhashed_key(key: &str) -> Option<PathBuf> {
+    let path = Path::new(key);
+    let variant = path.extension().filter(|extension| {
+        *extension == "map"
+            || Encoding::ALL
+                .iter()
+                .any(|encoding| *extension == encoding.extension())
+    });
+    let Some(extension) = variant else {
+        return super::unhashed_path(path);
+    };
+
+    let mut unhashed = super::unhashed_path(&path.with_extension(""))?.into_os_string();
+    unhashed.push(".");
+    unhashed.push(extension);
+    Some(unhashed.into())
+}
+
+/// What was uploaded by [`AssetBucket::send_assets`] (or
+/// [`AssetBucket::send_files`]), for a CI job to tell a partial deploy apart.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeployReport {
+    /// The number of objects uploaded.
+    pub uploaded: usize,
+    /// The number of objects not uploaded: excluded from the deploy, or
+    /// already uploaded by the resumed deploy.
+    pub skipped: usize,
+    /// The keys of the objects that could not be uploaded, with the reason.
+    pub failed: Vec<(String, String)>,
+    /// The bytes uploaded.
+    pub bytes: u64,
+}
+
+impl DeployReport {
+    /// Add the objects of `other`, e.g., of another bucket.
+    pub fn merge(&mut self, other: DeployReport) {
+        self.uploaded += other.uploaded;
+        self.skipped += other.skipped;
+        self.failed.extend(other.failed);
+        self.bytes += other.bytes;
+    }
+
+    /// Fail with the objects that could not be uploaded, if any.
+    pub fn check(&self) -> Result<(), Error> {
+        if self.failed.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::CannotUploadAssets(self.failed.clone()))
+        }
+    }
+}
+
+impl Display for DeployReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} object(s) uploaded ({} bytes), {} skipped, {} failed",
+            self.uploaded,
+            self.bytes,
+            self.skipped,
+            self.failed.len()
+        )
+    }
+}
+
+/// The differences between a local file and its uploaded copy: its size and
+/// content type, as returned by a HEAD (`None` if not found).
+fn compare_object(
+    key: &str,
+    expected_size: u64,
+    expected_type: &str,
+    uploaded: Option<(i64, Option<&str>)>,
+) -> Vec<VerifyMismatch> {
+    let Some((size, content_type)) = uploaded else {
+        return vec![VerifyMismatch::Missing {
+            key: key.to_owned(),
+        }];
+    };
+
+    let mut mismatches = Vec::new();
+    if size as u64 != expected_size {
+        mismatches.push(VerifyMismatch::WrongSize {
+            key: key.to_owned(),
+            expected: expected_size,
+            actual: size,
+        });
+    }
+    if content_type != Some(expected_type) {
+        mismatches.push(VerifyMismatch::WrongContentType {
+            key: key.to_owned(),
+            expected: expected_type.to_owned(),
+            actual: content_type.map(str::to_owned),
+        });
+    }
+    mismatches
+}
+
+/// A difference between an asset and its uploaded copy, see
+/// [`AssetBucket::verify`].
+#[derive(Debug)]
+pub enum VerifyMismatch {
+    Missing {
+        key: String,
+    },
+    Unreachable {
+        key: String,
+        error: String,
+    },
+    WrongSize {
+        key: String,
+        expected: u64,
+        actual: i64,
+    },
+    WrongContentType {
+        key: String,
+        expected: String,
+        actual: Option<String>,
+    },
+}
+
+impl Display for VerifyMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing { key } => write!(f, "'{key}' is missing from the bucket"),
+            Self::Unreachable { key, error } => write!(f, "'{key}' is not reachable: {error}"),
+            Self::WrongSize {
+                key,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "'{key}' has a size of {actual} bytes instead of {expected}"
+            ),
+            Self::WrongContentType {
+                key,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "'{key}' has a content-type of '{}' instead of '{expected}'",
+                actual.as_deref().unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+/// Compile the globs into a single set. Invalid globs are ignored (with a
+/// warning).
+fn build_glob_set(globs: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        match Glob::new(glob) {
+            Ok(glob) => {
+                builder.add(glob);
             }
+            Err(e) => warn!(target: LOG_TARGET, "Ignoring invalid glob '{glob}': {e}"),
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        warn!(target: LOG_TARGET, "Could not build the globs: {e}");
+        GlobSet::empty()
+    })
+}
+
+/// Whether the name of the asset at `path` has its hash: its content never
+/// changes. A pre-compressed variant is hashed like its asset.
+fn is_hashed(path: &Path) -> bool {
+    let decoded = match Encoding::from_path(path) {
+        Some(_) => path.with_extension(""),
+        None => path.to_owned(),
+    };
+    super::unhashed_path(&decoded).is_some()
+}
+
+/// The object key of `path`: S3 keys are separated by `/`, even when the path
+/// comes from Windows (e.g., `css\app.css`).
+pub fn to_object_key(path: &Path) -> String {
+    common::to_slash(path).replace('\\', "/")
+}
+
+/// Split a file of `size` bytes in parts of at most `part_size` bytes.
+/// Returns the (1-based) part number, the offset and the length of each part.
+fn part_ranges(size: u64, part_size: u64) -> impl Iterator<Item = (i32, u64, u64)> {
+    (0..size)
+        .step_by(part_size as usize)
+        .enumerate()
+        .map(move |(index, offset)| (index as i32 + 1, offset, part_size.min(size - offset)))
+}
+
+/// Stream `length` bytes of `src`, starting at `offset`, from the disk.
+async fn part_stream(
+    src: &Path,
+    offset: u64,
+    length: u64,
+) -> Result<ByteStream, aws_smithy_http::byte_stream::error::Error> {
+    ByteStream::read_from()
+        .path(src)
+        .offset(offset)
+        .length(Length::Exact(length))
+        .build()
+        .await
+}
+
+/// The headers of an uploaded object.
+struct ObjectHeaders<'a> {
+    content_type: &'a str,
+    content_encoding: Option<&'static str>,
+    cache_control: &'a str,
+}
+
+/// The wait before the `attempt`th retry of an upload: exponential, plus up
+/// to 50% of jitter so the failed uploads do not all retry at once.
+fn retry_delay(attempt: u32) -> Duration {
+    let delay = UPLOAD_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_UPLOAD_RETRY_DELAY);
+    // Good enough randomness for a jitter: a randomly seeded hash.
+    let random = RandomState::new().hash_one(attempt);
+    let jitter_ms = random % (delay.as_millis() as u64 / 2 + 1);
+    delay + Duration::from_millis(jitter_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Seek, SeekFrom, Write};
+
+    #[test]
+    fn retry_delays_grow_exponentially() {
+        let within = |attempt, min_ms| {
+            let delay = retry_delay(attempt);
+            delay >= Duration::from_millis(min_ms) && delay <= Duration::from_millis(min_ms * 3 / 2)
+        };
+        assert!(within(1, 500));
+        assert!(within(2, 1000));
+        assert!(within(4, 4000));
+        assert!(within(100, 30_000));
+    }
+
+    #[test]
+    fn part_ranges_cover_the_whole_file() {
+        let size = 2 * DEFAULT_UPLOAD_PART_SIZE + 1;
+        let parts: Vec<_> = part_ranges(size, DEFAULT_UPLOAD_PART_SIZE).collect();
+
+        assert_eq!(
+            parts,
+            vec![
+                (1, 0, DEFAULT_UPLOAD_PART_SIZE),
+                (2, DEFAULT_UPLOAD_PART_SIZE, DEFAULT_UPLOAD_PART_SIZE),
+                (3, 2 * DEFAULT_UPLOAD_PART_SIZE, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn cors_rules_fall_back_to_the_defaults() {
+        let rule: CorsRuleSpec = toml::from_str(
+            r#"
+            allowed_origins = ["https://example.com"]
+            allowed_methods = ["GET", "HEAD", "POST"]
+            max_age_seconds = 86400
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rule.allowed_methods, ["GET", "HEAD", "POST"]);
+        assert_eq!(rule.max_age_seconds, 86400);
+        assert_eq!(
+            rule.allowed_headers,
+            CorsRuleSpec::default().allowed_headers
+        );
+        assert_eq!(rule.expose_headers, CorsRuleSpec::default().expose_headers);
+    }
+
+    #[test]
+    fn cors_configuration_has_all_the_methods() {
+        let params = AssetsBucketParams {
+            cors_rules: vec![CorsRuleSpec::new(["https://example.com"])],
+            ..AssetsBucketParams::new("assets", "fr-par")
+        };
+        let bucket = AssetBucket::with_aws_config(&SdkConfig::builder().build(), &params);
+
+        let rules = bucket.cors_config.cors_rules().unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].allowed_methods().unwrap(), ["GET", "HEAD"]);
+        assert_eq!(rules[0].allowed_origins().unwrap(), ["https://example.com"]);
+    }
+
+    #[test]
+    fn unknown_storage_classes_are_refused() {
+        let params = |storage_class: &str| AssetsBucketParams {
+            storage_class: Some(storage_class.into()),
+            ..AssetsBucketParams::new("assets", "fr-par")
+        };
+
+        assert!(params("STANDARD_IA").validate().is_ok());
+        assert!(matches!(
+            params("STANDARD-IA").validate(),
+            Err(Error::UnknownStorageClass(value)) if value == "STANDARD-IA"
+        ));
+    }
+
+    #[test]
+    fn deploy_exclude_globs_match_processed_paths() {
+        let set = build_glob_set(&["**/*.map".to_owned(), "[".to_owned()]);
+
+        assert!(set.is_match("css/app-0123.css.map"));
+        assert!(!set.is_match("css/app-0123.css"));
+    }
+
+    #[test]
+    fn compare_the_uploaded_objects() {
+        let key = "css/app-000000000000000c.css.gz";
+        assert!(compare_object(key, 10, "text/css", Some((10, Some("text/css")))).is_empty());
+        assert!(matches!(
+            compare_object(key, 10, "text/css", None)[..],
+            [VerifyMismatch::Missing { .. }]
+        ));
+        assert!(matches!(
+            compare_object(key, 10, "text/css", Some((12, Some("text/css"))))[..],
+            [VerifyMismatch::WrongSize {
+                expected: 10,
+                actual: 12,
+                ..
+            }]
+        ));
+        assert!(matches!(
+            &compare_object(key, 10, "text/css", Some((12, None)))[..],
+            [
+                VerifyMismatch::WrongSize { .. },
+                VerifyMismatch::WrongContentType { actual: None, .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn only_the_old_versions_are_stale() {
+        let current = HashSet::from([
+            "css/app-000000000000000c.css".to_owned(),
+            "css/app-000000000000000c.css.gz".to_owned(),
+            "css/app-000000000000000c.css.map".to_owned(),
+        ]);
+        let objects = vec![
+            ("css/app-000000000000000c.css".to_owned(), 40),
+            ("css/app-000000000000000c.css.gz".to_owned(), 40),
+            ("css/app-000000000000000c.css.map".to_owned(), 40),
+            ("css/app-000000000000000b.css".to_owned(), 30),
+            ("css/app-000000000000000b.css.gz".to_owned(), 30),
+            ("css/app-000000000000000b.css.br".to_owned(), 30),
+            ("css/app-000000000000000b.css.map".to_owned(), 30),
+            ("css/app-000000000000000a.css".to_owned(), 20),
+            ("css/app-000000000000000a.css.gz".to_owned(), 20),
+            ("css/app-0000000000000009.css".to_owned(), 10),
+            ("css/app.css".to_owned(), 0),
+            ("css/gone-0000000000000001.css".to_owned(), 5),
+            ("css/gone-0000000000000002.css".to_owned(), 6),
+        ];
+
+        assert_eq!(
+            stale_objects(&current, objects.clone(), 1),
+            [
+                "css/app-0000000000000009.css",
+                "css/app-000000000000000a.css",
+                "css/app-000000000000000a.css.gz",
+                "css/gone-0000000000000001.css",
+            ]
+        );
+        assert_eq!(stale_objects(&current, objects.clone(), 3), [""; 0]);
+        assert_eq!(stale_objects(&current, objects, 0).len(), 9);
+    }
+
+    #[test]
+    fn headers_of_the_uploaded_objects() {
+        let params = AssetsBucketParams {
+            content_type_overrides: HashMap::from([(
+                ".WebManifest".to_owned(),
+                "application/manifest+json".to_owned(),
+            )]),
+            ..AssetsBucketParams::new("assets", "fr-par")
+        };
+        let bucket = AssetBucket::with_aws_config(&SdkConfig::builder().build(), &params);
+        let headers = |path: &str| {
+            let headers = bucket.object_headers(Path::new(path));
+            (
+                headers.content_type,
+                headers.content_encoding,
+                headers.cache_control.to_owned(),
+            )
+        };
+
+        assert_eq!(
+            headers("dist/css/app-0123456789abcdef.css"),
+            ("text/css", None, DEFAULT_CACHE_CONTROL.to_owned())
+        );
+        assert_eq!(
+            headers("dist/css/app-0123456789abcdef.css.br"),
+            ("text/css", Some("br"), DEFAULT_CACHE_CONTROL.to_owned())
+        );
+        assert_eq!(
+            headers("dist/css/app.css.gz"),
+            ("text/css", Some("gzip"), MUTABLE_CACHE_CONTROL.to_owned())
+        );
+        assert_eq!(
+            headers("dist/site.webmanifest"),
+            (
+                "application/manifest+json",
+                None,
+                MUTABLE_CACHE_CONTROL.to_owned()
+            )
+        );
+        assert_eq!(
+            headers("dist/data/blob-0123456789abcdef.unknown-ext"),
+            (DEFAULT_CONTENT_TYPE, None, DEFAULT_CACHE_CONTROL.to_owned())
+        );
+    }
+
+    #[test]
+    fn object_keys_use_forward_slashes() {
+        assert_eq!(
+            to_object_key(Path::new("css\\main-0123456789abcdef.css")),
+            "css/main-0123456789abcdef.css"
+        );
+        assert_eq!(
+            to_object_key(&Path::new("images").join("logo.png")),
+            "images/logo.png"
+        );
+        assert_eq!(to_object_key(Path::new("app.css")), "app.css");
+    }
+
+    #[test]
+    fn object_keys_have_the_prefix() {
+        let object_key = |key_prefix: Option<&str>| {
+            let params = AssetsBucketParams {
+                key_prefix: key_prefix.map(str::to_owned),
+                ..AssetsBucketParams::new("assets", "fr-par")
+            };
+            AssetBucket::with_aws_config(&SdkConfig::builder().build(), &params)
+                .object_key(&Path::new("css").join("app.css"))
+        };
+
+        assert_eq!(object_key(Some("/a/b/")), "a/b/css/app.css");
+        assert_eq!(object_key(Some("a\\b")), "a/b/css/app.css");
+        assert_eq!(object_key(Some("")), "css/app.css");
+        assert_eq!(object_key(Some("/")), "css/app.css");
+        assert_eq!(object_key(None), "css/app.css");
+    }
+
+    #[test]
+    fn only_the_hashed_objects_can_be_skipped() {
+        assert!(is_hashed(Path::new("css/app-0123456789abcdef.css")));
+        assert!(is_hashed(Path::new("css/app-0123456789abcdef.css.br")));
+        assert!(!is_hashed(Path::new("css/app.css")));
+        assert!(!is_hashed(Path::new("robots.txt.gz")));
+    }
+
+    #[tokio::test]
+    async fn part_stream_only_reads_its_part() {
+        // A sparse file: big on paper, but nothing is written to the disk.
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let size = 4 * MIN_UPLOAD_PART_SIZE + 42;
+        file.as_file_mut().seek(SeekFrom::Start(size - 1)).unwrap();
+        file.as_file_mut().write_all(&[0]).unwrap();
+
+        for (_, offset, length) in part_ranges(size, MIN_UPLOAD_PART_SIZE) {
+            let stream = part_stream(file.path(), offset, length).await.unwrap();
+            assert_eq!(stream.into_inner().content_length(), Some(length));
         }
     }
 }
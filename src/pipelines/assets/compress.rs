@@ -0,0 +1,188 @@
+//! Pre-compressed variants of the text assets (e.g., `app-<hash>.css.br` next
+//! to `app-<hash>.css`), see [`PacklerConfig::precompress`]. They are uploaded
+//! with their `Content-Encoding`, a CDN can serve them as is.
+
+use crate::{common, PacklerConfig};
+use flate2::{write::GzEncoder, Compression};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The `log` target of this pipeline, see [`crate::pipelines`].
+pub const LOG_TARGET: &str = "packler::compress";
+
+/// The best brotli compression: the assets are compressed once, and served
+/// many times.
+const BROTLI_QUALITY: u32 = 11;
+const BROTLI_WINDOW: u32 = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 2] = [Encoding::Gzip, Encoding::Brotli];
+
+    /// The extension appended to the compressed file name.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Brotli => "br",
+        }
+    }
+
+    /// The value of the `Content-Encoding` header.
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Brotli => "br",
+        }
+    }
+
+    /// The encoding of a pre-compressed file, from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?;
+        Self::ALL
+            .into_iter()
+            .find(|encoding| extension == encoding.extension())
+    }
+
+    fn compress(&self, content: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(content)?;
+                encoder.finish()
+            }
+            Self::Brotli => {
+                let mut compressed = Vec::new();
+                let mut encoder = brotli::CompressorWriter::new(
+                    &mut compressed,
+                    4096,
+                    BROTLI_QUALITY,
+                    BROTLI_WINDOW,
+                );
+                encoder.write_all(content)?;
+                drop(encoder);
+                Ok(compressed)
+            }
+        }
+    }
+}
+
+/// Write the compressed variants of `processed_relative_path` next to it.
+///
+/// An existing variant of a hashed asset is up to date, it is not compressed
+/// again. The assets at a fixed name (see [`PacklerConfig::hash_exclude`]) are
+/// always compressed again: their content may have changed.
+///
+/// Returns the variants, relative to [`PacklerConfig::dist_dir`].
+pub fn precompress(
+    config: &PacklerConfig,
+    processed_relative_path: &Path,
+) -> std::io::Result<Vec<(Encoding, PathBuf)>> {
+    let src = config.dist_dir.join(processed_relative_path);
+    let mut content = None;
+    let mut variants = Vec::new();
+    let hashed = super::unhashed_path(processed_relative_path).is_some();
+
+    for encoding in Encoding::ALL {
+        let mut variant = processed_relative_path.as_os_str().to_owned();
+        variant.push(".");
+        variant.push(encoding.extension());
+        let variant = PathBuf::from(variant);
+        let dest = config.dist_dir.join(&variant);
+
+        if config.dry_run {
+            info!(target: LOG_TARGET, "DRY RUN: would write '{}'", dest.display());
+        } else if hashed && dest.exists() {
+            debug!(target: LOG_TARGET, "'{}' is up to date", dest.display());
+        } else {
+            if content.is_none() {
+                content = Some(std::fs::read(&src)?);
+            }
+            let compressed = encoding.compress(content.as_deref().unwrap_or_default())?;
+            std::fs::write(&dest, compressed)?;
+            common::record_write(&dest);
+        }
+        variants.push((encoding, variant));
+    }
+
+    Ok(variants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn variants_decompress_to_the_asset() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            dist_dir: dir.path().to_owned(),
+            ..PacklerConfig::default()
+        };
+        let css = "body { color: red; }\n".repeat(100);
+        std::fs::create_dir_all(dir.path().join("css")).unwrap();
+        std::fs::write(dir.path().join("css/app-0123456789abcdef.css"), &css).unwrap();
+
+        let variants = precompress(&config, Path::new("css/app-0123456789abcdef.css")).unwrap();
+        assert_eq!(
+            variants,
+            [
+                (
+                    Encoding::Gzip,
+                    PathBuf::from("css/app-0123456789abcdef.css.gz")
+                ),
+                (
+                    Encoding::Brotli,
+                    PathBuf::from("css/app-0123456789abcdef.css.br")
+                ),
+            ]
+        );
+
+        let read = |path: &PathBuf| std::fs::read(dir.path().join(path)).unwrap();
+        let mut gzip = String::new();
+        GzDecoder::new(read(&variants[0].1).as_slice())
+            .read_to_string(&mut gzip)
+            .unwrap();
+        assert_eq!(gzip, css);
+        let mut brotli = String::new();
+        brotli::Decompressor::new(read(&variants[1].1).as_slice(), 4096)
+            .read_to_string(&mut brotli)
+            .unwrap();
+        assert_eq!(brotli, css);
+        assert_eq!(Encoding::from_path(&variants[1].1), Some(Encoding::Brotli));
+    }
+
+    #[test]
+    fn unhashed_assets_are_compressed_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            dist_dir: dir.path().to_owned(),
+            ..PacklerConfig::default()
+        };
+        std::fs::create_dir_all(dir.path().join("css")).unwrap();
+        let gunzip = |path: &PathBuf| {
+            let mut content = String::new();
+            GzDecoder::new(std::fs::read(dir.path().join(path)).unwrap().as_slice())
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+
+        std::fs::write(dir.path().join("css/app.css"), "a { color: red; }").unwrap();
+        precompress(&config, Path::new("css/app.css")).unwrap();
+        std::fs::write(dir.path().join("css/app.css"), "a { color: blue; }").unwrap();
+        let variants = precompress(&config, Path::new("css/app.css")).unwrap();
+        assert_eq!(gunzip(&variants[0].1), "a { color: blue; }");
+    }
+}
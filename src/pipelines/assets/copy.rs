@@ -0,0 +1,175 @@
+//! Copy the other assets (e.g., fonts, `robots.txt`, `favicon.ico`) of the
+//! [`PacklerConfig::copy_dirs`] to the dist directory, hashed like the images
+//! but otherwise untouched.
+
+use super::{incremental::Incremental, AssetMetadata, Error, HashExclude};
+use crate::progress::{self, Phase};
+use crate::{common, PacklerConfig};
+use log::{debug, info, trace, warn};
+use std::path::{Path, PathBuf};
+
+/// The `log` target of this pipeline, see [`crate::pipelines`].
+pub const LOG_TARGET: &str = "packler::copy";
+
+/// Hash and copy the files of the [`PacklerConfig::copy_dirs`] to the same
+/// directories of the dist directory.
+///
+/// With `incremental`, only the files that changed are copied again, the
+/// others keep their previous metadata.
+pub fn process(
+    config: &PacklerConfig,
+    incremental: Option<&Incremental>,
+) -> Result<Vec<AssetMetadata>, Box<dyn std::error::Error>> {
+    // An incremental build keeps the files that did not change.
+    if incremental.is_none() {
+        clean_dist_dir(config)?;
+    }
+
+    let hash_exclude = HashExclude::new(config)?;
+    let mut files = Vec::new();
+    for dir in &config.copy_dirs {
+        let source_dir = config.assets_source_dir.join(dir);
+        if !source_dir.is_dir() {
+            warn!(target: LOG_TARGET, "COPY: '{}' is not a directory. Skip", source_dir.display());
+            continue;
+        }
+
+        info!(target: LOG_TARGET, "COPY: copying the files of '{}'", source_dir.display());
+        for entry in common::walk_dir(&source_dir, config.include_hidden) {
+            let entry = entry?;
+            if entry.path().is_file() {
+                files.push(entry.into_path());
+            }
+        }
+    }
+
+    progress::start(Phase::Copy, files.len());
+    let mut assets = Vec::with_capacity(files.len());
+    for file in files {
+        assets.push(copy_file(config, incremental, &hash_exclude, &file)?);
+        progress::advance(Phase::Copy);
+    }
+    Ok(assets)
+}
+
+/// Copy the file at `path` to its hashed path in the dist directory.
+fn copy_file(
+    config: &PacklerConfig,
+    incremental: Option<&Incremental>,
+    hash_exclude: &HashExclude,
+    path: &Path,
+) -> Result<AssetMetadata, Box<dyn std::error::Error>> {
+    let content = std::fs::read(path)?;
+    let hash = seahash::hash(&content);
+
+    if let Some(previous) =
+        incremental.and_then(|incremental| incremental.unchanged(config, path, hash))
+    {
+        trace!(target: LOG_TARGET, "COPY: {} did not change. Skip", path.display());
+        return Ok(previous.clone());
+    }
+
+    let logical_path = path
+        .strip_prefix(&config.assets_source_dir)
+        .map_err(|_| format!("not in '{}'", config.assets_source_dir.display()))?
+        .to_owned();
+    let processed_relative_path = hash_exclude.processed_path(&logical_path, hash);
+    let dest = config.dist_dir.join(&processed_relative_path);
+    debug!(
+        target: LOG_TARGET,
+        "COPY: {} to {}",
+        path.display(),
+        dest.display()
+    );
+
+    if config.dry_run {
+        info!(target: LOG_TARGET, "DRY RUN: would write '{}'", dest.display());
+    } else {
+        if let Some(dir) = dest.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::copy(path, &dest)?;
+        common::record_write(&dest);
+    }
+
+    Ok(AssetMetadata {
+        source_hash: hash,
+        ..AssetMetadata::new(
+            path,
+            logical_path,
+            processed_relative_path,
+            hash,
+            config.integrity_algorithm.integrity(&content),
+        )
+    })
+}
+
+/// The directories of the dist directory the files are copied to.
+fn dist_dirs(config: &PacklerConfig) -> impl Iterator<Item = PathBuf> + '_ {
+    config.copy_dirs.iter().map(|dir| config.dist_dir.join(dir))
+}
+
+pub fn clean_dist_dir(config: &PacklerConfig) -> Result<(), Error> {
+    for dir in dist_dirs(config).filter(|dir| dir.exists()) {
+        if config.dry_run {
+            info!(target: LOG_TARGET, "DRY RUN: would remove '{}'", dir.display());
+        } else {
+            std::fs::remove_dir_all(&dir).map_err(Error::CannotRemoveDistDir)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipelines::assets::hashed_path;
+
+    #[test]
+    fn files_are_hashed_and_copied() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            copy_dirs: vec![
+                "fonts".to_owned(),
+                "static".to_owned(),
+                "missing".to_owned(),
+            ],
+            hash_exclude: vec!["robots.txt".to_owned()],
+            ..PacklerConfig::default()
+        };
+        let fonts = config.assets_source_dir.join("fonts");
+        std::fs::create_dir_all(fonts.join("inter")).unwrap();
+        std::fs::write(fonts.join("inter").join("inter.woff2"), "woff2").unwrap();
+        std::fs::write(fonts.join(".DS_Store"), "").unwrap();
+        let statics = config.assets_source_dir.join("static");
+        std::fs::create_dir_all(&statics).unwrap();
+        std::fs::write(statics.join("robots.txt"), "User-agent: *").unwrap();
+        // Removed by a full build.
+        std::fs::create_dir_all(config.dist_dir.join("fonts")).unwrap();
+        std::fs::write(config.dist_dir.join("fonts").join("old.woff2"), "").unwrap();
+
+        let assets = process(&config, None).unwrap();
+        assert_eq!(assets.len(), 2);
+        assert_eq!(
+            assets[0].logical_path,
+            Path::new("fonts").join("inter").join("inter.woff2")
+        );
+        assert_eq!(
+            assets[0].processed_relative_path,
+            hashed_path(&assets[0].logical_path, seahash::hash(b"woff2"))
+        );
+        let dest = config.dist_dir.join(&assets[0].processed_relative_path);
+        assert_eq!(std::fs::read_to_string(dest).unwrap(), "woff2");
+        assert!(!config.dist_dir.join("fonts").join("old.woff2").exists());
+
+        // Still hashed in the metadata.
+        assert_eq!(
+            assets[1].processed_relative_path,
+            Path::new("static").join("robots.txt")
+        );
+        assert_eq!(assets[1].hash, seahash::hash(b"User-agent: *"));
+        assert!(config.dist_dir.join("static").join("robots.txt").exists());
+    }
+}
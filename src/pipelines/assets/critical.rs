@@ -0,0 +1,124 @@
+//! Extract the critical (above-the-fold) CSS of a page.
+//!
+//! The flow is as followed:
+//!
+//! - find the compiled CSS of the configured SASS entrypoint
+//! - run `critical` over the HTML page and that CSS
+//! - hash the output and write it to dist/css/critical-<hash>.css
+//!
+
+use crate::common;
+use crate::pipelines::assets::{AssetMetadata, Error};
+use crate::tools::{self, Application};
+use crate::PacklerConfig;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The `log` target of this pipeline, see [`crate::pipelines`].
+pub const LOG_TARGET: &str = "packler::critical";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CriticalCssConfig {
+    /// The SASS entrypoint whose compiled CSS is analysed. Relative to
+    /// [`PacklerConfig::source_sass_dir`], like the SASS entrypoints.
+    pub entrypoint: PathBuf,
+
+    /// The HTML page used to determine what is above the fold.
+    pub html: PathBuf,
+
+    /// Also store the critical CSS itself in the metadata file so a server
+    /// can inline it without reading another file.
+    #[serde(default)]
+    pub inline_in_metadata: bool,
+
+    /// The version of `critical` to use. Default: any system installed one.
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CriticalCssMetadata {
+    #[serde(flatten)]
+    pub asset: AssetMetadata,
+
+    /// The raw critical CSS, only set if
+    /// [`CriticalCssConfig::inline_in_metadata`] is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inline: Option<String>,
+}
+
+/// Run `critical` on the compiled CSS of the configured entrypoint. Must be
+/// called once the SASS pipeline produced `sass`.
+///
+/// Returns `None` in a dry run: the compiled CSS was not written.
+pub async fn process(
+    config: &PacklerConfig,
+    critical: &CriticalCssConfig,
+    sass: &[AssetMetadata],
+) -> Result<Option<CriticalCssMetadata>, Box<dyn std::error::Error>> {
+    info!(target: LOG_TARGET, "CRITICAL: Start critical CSS Pipeline");
+
+    if config.dry_run {
+        info!(
+            target: LOG_TARGET,
+            "DRY RUN: would extract the critical CSS of '{}'",
+            critical.html.display()
+        );
+        return Ok(None);
+    }
+
+    let source_path = config.source_sass_dir().join(&critical.entrypoint);
+    let Some(compiled) = sass.iter().find(|asset| asset.source_path == source_path) else {
+        return Err(Box::new(Error::EntryPointDoesNotExist(
+            critical.entrypoint.display().to_string(),
+        )));
+    };
+    let css_path = config.dist_dir.join(&compiled.processed_relative_path);
+
+    let bin = tools::get(
+        Application::Critical,
+        critical.version.as_deref(),
+        &config.tool_checksums,
+    )
+    .await?;
+
+    let args = &[
+        critical.html.display().to_string(),
+        "--css".to_owned(),
+        css_path.display().to_string(),
+    ];
+
+    info!(
+        target: LOG_TARGET,
+        "CRITICAL: extracting critical CSS of '{}'",
+        critical.html.display()
+    );
+    let css = common::run_command_output(Application::Critical.name(), &bin, args).await?;
+    let hash = seahash::hash(css.as_bytes());
+
+    let final_file_path = config
+        .dist_sass_dir()
+        .join(format!("critical-{hash:016x}.css"));
+
+    info!(target: LOG_TARGET, "CRITICAL: writing file to final destination '{final_file_path:?}'");
+
+    if let Some(dir) = final_file_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&final_file_path, &css)?;
+    common::record_write(&final_file_path);
+
+    let asset = AssetMetadata::new(
+        &critical.html,
+        PathBuf::from(&config.sass_dir_name).join("critical.css"),
+        final_file_path.strip_prefix(&config.dist_dir)?,
+        hash,
+        config.integrity_algorithm.integrity(css.as_bytes()),
+    );
+
+    Ok(Some(CriticalCssMetadata {
+        asset,
+        inline: critical.inline_in_metadata.then_some(css),
+    }))
+}
@@ -0,0 +1,472 @@
+//! Deploy the assets to an (S3 compatible) object storage, see
+//! [`PacklerParams::assets_bucket`]. Only with the `deploy` feature, the AWS
+//! SDK is a heavy dependency.
+//!
+//! Like [`super::tasks`], the async tasks are public with the `async` feature.
+
+use super::{read_metadata_file, tasks::build_assets_inner, write_metadata_file, Error};
+use crate::{
+    common,
+    hooks::{run_hook, Hook},
+    pipelines::assets::bucket::{AssetBucket, AssetsBucketParams, DeployReport},
+    DeployOpts, PacklerConfig, PacklerParams, Profile, PruneOpts,
+};
+use aws_config::SdkConfig;
+use futures_util::future::join_all;
+use log::{error, info, warn};
+use std::path::PathBuf;
+
+/// Build and upload the assets, to [`PacklerParams::assets_bucket`] and to
+/// each of the [`PacklerParams::mirror_buckets`]. With
+/// [`DeployOpts::resume`], the assets already uploaded by an interrupted
+/// deploy of the same build are skipped.
+///
+/// The whole deploy fails if it takes longer than
+/// [`DeployOpts::deploy_timeout`].
+///
+/// Returns what was uploaded to all the buckets. The assets that could not be
+/// uploaded, even after [`DeployOpts::max_upload_retries`] retries, are in
+/// its [`DeployReport::failed`]: see [`DeployReport::check`]. With
+/// [`AssetsBucketParams::upload_metadata`], the metadata file is uploaded
+/// last, only once all the assets it points to are uploaded.
+pub async fn deploy_assets(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    opts: &DeployOpts,
+) -> Result<DeployReport, Error> {
+    let mut buckets = Vec::new();
+    for bucket_params in buckets_params(params)? {
+        buckets.push((bucket_params, open_bucket(cfg, bucket_params, opts).await));
+    }
+
+    let deploy = deploy_assets_with_buckets(params, cfg, &buckets, opts.resume);
+    match opts.deploy_timeout {
+        Some(timeout) => {
+            tokio::time::timeout(timeout, deploy)
+                .await
+                .map_err(|_| Error::DeployTimedOut {
+                    timeout,
+                    uploaded: buckets
+                        .iter()
+                        .map(|(_, bucket)| bucket.uploaded_count())
+                        .sum(),
+                })?
+        }
+        None => deploy.await,
+    }
+}
+
+/// Upload only `files`, already built in the dist directory, then the
+/// metadata file. Faster than [`deploy_assets`] to ship a hotfix.
+pub async fn deploy_files(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    opts: &DeployOpts,
+    files: &[PathBuf],
+) -> Result<DeployReport, Error> {
+    let buckets = buckets_params(params)?;
+
+    let dist_dir = cfg.dist_dir.canonicalize().map_err(Error::CannotReadFile)?;
+    let mut relative_paths = Vec::with_capacity(files.len());
+    for file in files {
+        let path = file.canonicalize().map_err(Error::CannotReadFile)?;
+        match path.strip_prefix(&dist_dir) {
+            Ok(relative) if path.is_file() => relative_paths.push(relative.to_owned()),
+            _ => return Err(Error::NotInDistDir(file.to_owned())),
+        }
+    }
+
+    let mut results = Vec::new();
+    for bucket_params in buckets {
+        let bucket = open_bucket(cfg, bucket_params, opts).await;
+        let result = async {
+            info!(
+                "uploading {} file(s) to '{}'",
+                relative_paths.len(),
+                bucket_params.bucket_name
+            );
+            let report = bucket.send_files(cfg, &relative_paths).await;
+            info!("'{}': {report}", bucket_params.bucket_name);
+            if !report.failed.is_empty() {
+                return Ok(report);
+            }
+
+            info!("uploading metadata file");
+            bucket
+                .send_metadata(cfg)
+                .await
+                .map_err(Error::CannotUploadMetadataFile)?;
+            Ok(report)
+        };
+        results.push((bucket_params, result.await));
+    }
+    Ok(merge_reports(check_buckets(results)?))
+}
+
+/// The buckets to deploy to: [`PacklerParams::assets_bucket`], then the
+/// [`PacklerParams::mirror_buckets`].
+fn buckets_params(params: &PacklerParams) -> Result<Vec<&AssetsBucketParams>, Error> {
+    let Some(bucket_params) = &params.assets_bucket else {
+        return Err(Error::MissingBucketParams);
+    };
+    let buckets: Vec<_> = std::iter::once(bucket_params)
+        .chain(&params.mirror_buckets)
+        .collect();
+    for bucket_params in &buckets {
+        bucket_params.validate()?;
+    }
+    Ok(buckets)
+}
+
+/// Fail with the buckets whose task failed. With a single bucket, its error is
+/// returned as is.
+///
+/// Returns the bucket names with their results otherwise.
+fn check_buckets<T>(
+    results: Vec<(&AssetsBucketParams, Result<T, Error>)>,
+) -> Result<Vec<(String, T)>, Error> {
+    let single = results.len() == 1;
+    let mut successes = Vec::new();
+    let mut failures: Vec<(String, Error)> = Vec::new();
+    for (bucket_params, result) in results {
+        let name = bucket_params.bucket_name.clone();
+        match result {
+            Ok(result) => successes.push((name, result)),
+            Err(e) => failures.push((name, e)),
+        }
+    }
+
+    match failures.pop() {
+        None => Ok(successes),
+        Some((_, e)) if single => Err(e),
+        Some(failure) => {
+            failures.push(failure);
+            Err(Error::BucketsFailed(failures))
+        }
+    }
+}
+
+/// Add up the reports of the buckets. With mirrors, the failed keys are
+/// prefixed with their bucket, e.g., `assets-eu/css/app.css`.
+fn merge_reports(reports: Vec<(String, DeployReport)>) -> DeployReport {
+    let single = reports.len() == 1;
+    let mut merged = DeployReport::default();
+    for (bucket_name, mut report) in reports {
+        if !single {
+            for (key, _) in &mut report.failed {
+                *key = format!("{bucket_name}/{key}");
+            }
+        }
+        merged.merge(report);
+    }
+    merged
+}
+
+/// The bucket to deploy to. A dry run needs no credentials, and makes no
+/// request to the bucket.
+async fn open_bucket(
+    cfg: &PacklerConfig,
+    bucket_params: &AssetsBucketParams,
+    opts: &DeployOpts,
+) -> AssetBucket {
+    let bucket = if cfg.dry_run {
+        AssetBucket::with_aws_config(&SdkConfig::builder().build(), bucket_params)
+    } else {
+        AssetBucket::new(bucket_params).await
+    };
+    bucket
+        .with_max_upload_retries(opts.max_upload_retries)
+        .with_dry_run(cfg.dry_run)
+}
+
+/// The assets are built once, with [`Profile::Release`] like the other
+/// components, and uploaded to all the buckets at the same time.
+async fn deploy_assets_with_buckets(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    buckets: &[(&AssetsBucketParams, AssetBucket)],
+    resume: bool,
+) -> Result<DeployReport, Error> {
+    info!("building assets");
+    let metadata = build_assets_inner(params, cfg, None, Profile::Release).await?;
+
+    info!("uploading assets");
+    let uploads = buckets
+        .iter()
+        .map(|(_, bucket)| bucket.send_assets(cfg, &metadata, resume));
+    let uploaded = join_all(uploads).await;
+
+    info!("writing metadata file");
+    write_metadata_file(cfg, &metadata)?;
+
+    let mut results = Vec::new();
+    for ((bucket_params, bucket), report) in buckets.iter().zip(uploaded) {
+        let result = finish_deploy(cfg, bucket_params, bucket, report).await;
+        results.push((*bucket_params, result));
+    }
+    let report = merge_reports(check_buckets(results)?);
+
+    // The deploy is not complete.
+    if !report.failed.is_empty() {
+        return Ok(report);
+    }
+    run_hook(cfg, Hook::PostDeploy)
+        .await
+        .map_err(|e| Error::HookFailed(Hook::PostDeploy, e))?;
+    Ok(report)
+}
+
+/// Once its assets are uploaded, upload the metadata file to a bucket and set
+/// its CORS config. Skipped when some assets could
+/// not be uploaded: the metadata would point to missing assets.
+async fn finish_deploy(
+    cfg: &PacklerConfig,
+    bucket_params: &AssetsBucketParams,
+    bucket: &AssetBucket,
+    report: DeployReport,
+) -> Result<DeployReport, Error> {
+    info!("'{}': {report}", bucket_params.bucket_name);
+    if !report.failed.is_empty() {
+        warn!(
+            "'{}' is missing some assets, its metadata file is not uploaded",
+            bucket_params.bucket_name
+        );
+        return Ok(report);
+    }
+
+    if bucket_params.upload_metadata {
+        info!("uploading metadata file to '{}'", bucket_params.bucket_name);
+        bucket
+            .send_metadata(cfg)
+            .await
+            .map_err(Error::CannotUploadMetadataFile)?;
+    }
+
+    bucket.send_cors().await;
+    Ok(report)
+}
+
+/// List the stale objects of the buckets, and delete them with
+/// [`PruneOpts::delete`]. See [`AssetBucket::prune_assets`].
+pub async fn prune_assets(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    opts: &PruneOpts,
+) -> Result<(), Error> {
+    let buckets = buckets_params(params)?;
+    let metadata = read_metadata_file(cfg)?;
+
+    let mut results = Vec::new();
+    for bucket_params in buckets {
+        let bucket = AssetBucket::new(bucket_params).await;
+        let result = bucket
+            .prune_assets(cfg, &metadata, opts.keep_versions, opts.delete)
+            .await
+            .map_err(Error::CannotPruneBucket)
+            .map(|stale| {
+                let name = &bucket_params.bucket_name;
+                if opts.delete && !cfg.dry_run {
+                    info!("{} stale object(s) deleted from '{name}'", stale.len());
+                } else {
+                    info!(
+                        "{} stale object(s) found in '{name}', use --prune-delete to delete them",
+                        stale.len()
+                    );
+                }
+            });
+        results.push((bucket_params, result));
+    }
+    check_buckets(results).map(|_| ())
+}
+
+/// Check that the assets of the last deploy are really in the buckets. See
+/// [`AssetBucket::verify`].
+pub async fn verify_assets(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    sample: usize,
+) -> Result<(), Error> {
+    let buckets = buckets_params(params)?;
+    let metadata = read_metadata_file(cfg)?;
+
+    let mut results = Vec::new();
+    for bucket_params in buckets {
+        let bucket = AssetBucket::new(bucket_params).await;
+        let mismatches = bucket.verify(cfg, &metadata, sample).await;
+
+        for mismatch in mismatches.iter() {
+            error!("{}: {mismatch}", bucket_params.bucket_name);
+        }
+
+        let result = if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::DeployVerificationFailed(mismatches.len()))
+        };
+        results.push((bucket_params, result));
+    }
+    check_buckets(results).map(|_| ())
+}
+
+/// Build and upload the assets. With [`DeployOpts::resume`], the assets
+/// already uploaded by an interrupted deploy of the same build are skipped.
+///
+/// Blocks on its own runtime: it panics if called from within one, use
+/// `deploy_assets` (`async` feature) there.
+///
+/// ```no_run
+/// use packler::pipelines::assets::{bucket::AssetsBucketParams, deploy_assets_blocking};
+/// use packler::{DeployOpts, PacklerConfig, PacklerParams};
+///
+/// # fn deploy() -> Result<(), packler::pipelines::assets::Error> {
+/// let params = PacklerParams::new(["main.scss"], ["frontend"], Some("backend"))
+///     .with_assets_bucket(AssetsBucketParams {
+///         bucket_endpoint_url: Some("https://s3.fr-par.scw.cloud".to_owned()),
+///         upload_metadata: true,
+///         ..AssetsBucketParams::new("bucket-with-assets", "fr-par")
+///     });
+///
+/// let report = deploy_assets_blocking(
+///     &params,
+///     &PacklerConfig::default(),
+///     &DeployOpts::default(),
+/// )?;
+/// println!("{report}");
+/// report.check()
+/// # }
+/// ```
+pub fn deploy_assets_blocking(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    opts: &DeployOpts,
+) -> Result<DeployReport, Error> {
+    common::block_on(deploy_assets(params, cfg, opts))
+}
+
+/// Upload only `files`, already built in the dist directory, then the
+/// metadata file. Blocks on its own runtime: it panics if called from within
+/// one.
+pub fn deploy_files_blocking(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    opts: &DeployOpts,
+    files: &[PathBuf],
+) -> Result<DeployReport, Error> {
+    common::block_on(deploy_files(params, cfg, opts, files))
+}
+
+/// List the stale objects of the bucket, and delete them with
+/// [`PruneOpts::delete`]. Blocks on its own runtime: it panics if called from
+/// within one.
+pub fn prune_assets_blocking(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    opts: &PruneOpts,
+) -> Result<(), Error> {
+    common::block_on(prune_assets(params, cfg, opts))
+}
+
+/// Check that the assets of the last deploy are really in the bucket. Blocks
+/// on its own runtime: it panics if called from within one.
+pub fn verify_assets_blocking(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    sample: usize,
+) -> Result<(), Error> {
+    common::block_on(verify_assets(params, cfg, sample))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::types::StorageClass;
+
+    #[tokio::test]
+    async fn deploy_reads_the_bucket_params() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            target: dir.path().join("target"),
+            dry_run: true,
+            ..PacklerConfig::default()
+        };
+        let params = PacklerParams::new([""; 0], [""; 0], None);
+        let opts = DeployOpts::default();
+
+        assert!(matches!(
+            deploy_assets(&params, &config, &opts).await,
+            Err(Error::MissingBucketParams)
+        ));
+
+        let bucket = |storage_class: &str| AssetsBucketParams {
+            storage_class: Some(StorageClass::from(storage_class)),
+            ..AssetsBucketParams::new("assets", "fr-par")
+        };
+        let params = params.with_assets_bucket(bucket("GLACIER_SOMETIMES"));
+        assert!(matches!(
+            deploy_assets(&params, &config, &opts).await,
+            Err(Error::UnknownStorageClass(_))
+        ));
+
+        // A dry run makes no request to the bucket, and writes nothing.
+        std::fs::create_dir_all(config.source_image_dir()).unwrap();
+        std::fs::write(config.source_image_dir().join("logo.svg"), "<svg/>").unwrap();
+        let params = params.with_assets_bucket(bucket("STANDARD_IA"));
+        let report = deploy_assets(&params, &config, &opts).await.unwrap();
+        assert_eq!(report.uploaded, 1);
+        assert_eq!(report.bytes, 0);
+        assert!(report.failed.is_empty());
+        assert!(!config.dist_dir.exists());
+        assert!(!config.intermediate_dir().exists());
+
+        let params = params.with_mirror_bucket(bucket("GLACIER_SOMETIMES"));
+        assert!(matches!(
+            deploy_assets(&params, &config, &opts).await,
+            Err(Error::UnknownStorageClass(_))
+        ));
+        let params = PacklerParams {
+            mirror_buckets: vec![],
+            ..params
+        }
+        .with_mirror_bucket(bucket("STANDARD"));
+        let report = deploy_assets(&params, &config, &opts).await.unwrap();
+        assert_eq!(report.uploaded, 2);
+    }
+
+    #[tokio::test]
+    async fn deploy_files_only_from_the_dist_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            target: dir.path().join("target"),
+            dry_run: true,
+            ..PacklerConfig::default()
+        };
+        let params = PacklerParams::new([""; 0], [""; 0], None)
+            .with_assets_bucket(AssetsBucketParams::new("assets", "fr-par"));
+        let opts = DeployOpts::default();
+        std::fs::create_dir_all(config.dist_dir.join("css")).unwrap();
+        let in_dist = config.dist_dir.join("css").join("app.css");
+        std::fs::write(&in_dist, "a{}").unwrap();
+        let outside = dir.path().join("app.css");
+        std::fs::write(&outside, "a{}").unwrap();
+
+        assert!(matches!(
+            deploy_files(&params, &config, &opts, &[in_dist.clone(), outside.clone()]).await,
+            Err(Error::NotInDistDir(path)) if path == outside
+        ));
+        // The dist directory itself is not a file to upload.
+        assert!(matches!(
+            deploy_files(&params, &config, &opts, &[config.dist_dir.join("css")]).await,
+            Err(Error::NotInDistDir(_))
+        ));
+
+        let report = deploy_files(&params, &config, &opts, &[in_dist])
+            .await
+            .unwrap();
+        assert_eq!(report.uploaded, 1);
+        assert!(report.failed.is_empty());
+    }
+}
@@ -1,9 +1,25 @@
-use super::AssetMetadata;
-use crate::PacklerConfig;
-use log::{debug, info, trace, warn};
+use super::{
+    hashed_path,
+    incremental::Incremental,
+    optimize::ImageOptimizer,
+    overrides::{ImageOverrides, OutputFormat, Variant},
+    placeholder::PlaceholderCache,
+    AssetMetadata, Error, HashExclude,
+};
+use crate::progress::{self, Phase};
+use crate::{common, PacklerConfig};
+use globset::GlobSet;
+use log::{debug, error, info, trace, warn};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use walkdir::WalkDir;
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+/// The `log` target of this pipeline, see [`crate::pipelines`].
+pub const LOG_TARGET: &str = "packler::images";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageProcessOutput {
@@ -12,79 +28,638 @@ pub struct ImageProcessOutput {
     pub files: Vec<AssetMetadata>,
 }
 
-pub fn process(config: &PacklerConfig) -> Result<Vec<AssetMetadata>, Box<dyn std::error::Error>> {
+/// An image of a `srcset`, see [`srcsets`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SrcsetCandidate {
+    pub processed_relative_path: PathBuf,
+    pub width: u32,
+}
+
+/// How a processed image gets to the dist directory.
+enum ImageOutput {
+    /// Unchanged since the previous (incremental) build, already there.
+    Keep,
+    /// Copied as is from the source.
+    Copy,
+    /// Generated from the source, see [`ImageOverrides`].
+    Write(Vec<u8>),
+}
+
+/// The images are processed in parallel: the errors must be `Send`.
+type ImageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Why an image cannot be collected. Unlike [`Error`], it can be sent from the
+/// threads processing the images.
+enum CollectError {
+    Read(PathBuf, std::io::Error),
+    Failed(ImageError),
+}
+
+impl From<ImageError> for CollectError {
+    fn from(source: ImageError) -> Self {
+        Self::Failed(source)
+    }
+}
+
+impl From<CollectError> for Box<dyn std::error::Error> {
+    fn from(source: CollectError) -> Self {
+        match source {
+            CollectError::Read(path, source) => Box::new(Error::ImageReadFailed(path, source)),
+            CollectError::Failed(source) => source,
+        }
+    }
+}
+
+/// Hash and copy the images to the dist directory.
+///
+/// Images with a sidecar file are resized and converted as it says, see
+/// [`ImageOverrides`]. PNG and JPEG images are also converted to the
+/// [`PacklerConfig::image_formats`], and first optimized by the `optimizer`
+/// if any: the hashes are the ones of the optimized images.
+///
+/// With `incremental`, only the images that changed (or whose hash changed)
+/// are copied again, the others keep their previous metadata.
+///
+/// The images are processed in parallel, but returned in the order they are
+/// found in. An image that cannot be processed does not stop the others: all
+/// the failures are reported at the end.
+pub fn process(
+    config: &PacklerConfig,
+    incremental: Option<&Incremental>,
+    optimizer: Option<&ImageOptimizer>,
+) -> Result<Vec<AssetMetadata>, Box<dyn std::error::Error>> {
     let images_dir = config.source_image_dir();
 
-    info!("IMG: Collecting all images metadata");
-    let images: Vec<AssetMetadata> = WalkDir::new(&images_dir)
-        .into_iter()
-        .filter_map(|entry| {
-            match entry {
-                Ok(entry) => {
-                    if entry.path().is_file() {
-                        let relative_path = entry
-                            .path()
-                            .strip_prefix(&config.assets_source_dir)
-                            .unwrap();
-
-                        debug!(
-                            "IMG: {} (relative: {})",
-                            entry.path().display(),
-                            relative_path.display()
-                        );
-
-                        let image_content = std::fs::read(entry.path()).unwrap();
-                        let hash = seahash::hash(&image_content);
-
-                        // file_stem() instead of file_prefix() otherwise we would
-                        // lose a component if there are two '.' in the filename.
-                        let hashed_name = format!(
-                            "{}-{:x}.{}",
-                            relative_path.file_stem().unwrap().to_string_lossy(),
-                            hash,
-                            relative_path.extension().unwrap().to_string_lossy()
-                        );
-
-                        Some(AssetMetadata {
-                            source_path: entry.path().to_owned(),
-                            logical_path: relative_path.to_owned(),
-                            processed_relative_path: relative_path.with_file_name(hashed_name),
-                            hash,
-                        })
-                    } else {
-                        trace!("{} is not a file. Skip", entry.path().display());
-                        None
-                    }
-                }
-                Err(e) => {
-                    warn!("Could not walk into images: {e}");
-                    None
-                }
+    let placeholders = config
+        .image_placeholders
+        .map(|_| PlaceholderCache::load(config));
+
+    let filter = ImageFilter::new(config)?;
+    let hash_exclude = HashExclude::new(config)?;
+
+    info!(target: LOG_TARGET, "IMG: Collecting all images metadata");
+    let paths: Vec<PathBuf> = common::walk_dir(&images_dir, config.include_hidden)
+        .filter_map(|entry| match entry {
+            Ok(entry) if !filter.is_included(&images_dir, entry.path()) => {
+                trace!(target: LOG_TARGET, "{} is excluded. Skip", entry.path().display());
+                None
+            }
+            Ok(entry) if entry.path().is_file() && !ImageOverrides::is_sidecar(entry.path()) => {
+                Some(entry.into_path())
+            }
+            Ok(entry) => {
+                trace!(
+                    target: LOG_TARGET,
+                    "{} is not an image. Skip",
+                    entry.path().display()
+                );
+                None
+            }
+            Err(e) => {
+                warn!(target: LOG_TARGET, "Could not walk into images: {e}");
+                None
             }
         })
         .collect();
 
-    info!("IMG: Cleaning destination directory");
-    clean_dist_dir(config);
+    progress::start(Phase::Images, paths.len());
+    let results: Vec<_> = paths
+        .par_iter()
+        .map(|path| {
+            let image = collect_image(
+                config,
+                incremental,
+                placeholders.as_ref(),
+                optimizer,
+                &hash_exclude,
+                path,
+            );
+            progress::advance(Phase::Images);
+            image
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|result| result.map_err(Box::<dyn std::error::Error>::from))
+        .collect();
+    let images = check_failures(&paths, results)?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<(AssetMetadata, ImageOutput)>>();
+
+    match placeholders {
+        Some(_) if config.dry_run => {
+            info!(target: LOG_TARGET, "DRY RUN: would save the placeholders cache")
+        }
+        Some(placeholders) => placeholders.save(),
+        None => {}
+    }
+    if let Some(optimizer) = optimizer {
+        optimizer.report();
+    }
+
+    // An incremental build keeps the images that did not change.
+    if incremental.is_none() {
+        info!(target: LOG_TARGET, "IMG: Cleaning destination directory");
+        clean_dist_dir(config)?;
+    }
 
     // Actual file copy
-    for image in images.iter() {
-        let dest_path = config.dist_dir.join(&image.processed_relative_path);
+    let results: Vec<_> = images
+        .par_iter()
+        .map(|(image, output)| write_image(config, image, output))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|result| result.map_err(|e| e as Box<dyn std::error::Error>))
+        .collect();
+    let sources: Vec<PathBuf> = images
+        .iter()
+        .map(|(image, _)| image.source_path.clone())
+        .collect();
+    check_failures(&sources, results)?;
+
+    Ok(images.into_iter().map(|(image, _)| image).collect())
+}
+
+/// Which files of the images directory are processed, see
+/// [`PacklerConfig::image_include`] and [`PacklerConfig::image_exclude`].
+struct ImageFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl ImageFilter {
+    fn new(config: &PacklerConfig) -> Result<Self, crate::config::Error> {
+        Ok(Self {
+            include: config.image_include_set()?,
+            exclude: config.image_exclude_set()?,
+        })
+    }
+
+    /// Whether the file at `path` (in `images_dir`) is processed. The
+    /// directories are always walked into.
+    fn is_included(&self, images_dir: &Path, path: &Path) -> bool {
+        if path.is_dir() {
+            return true;
+        }
+        let relative = path.strip_prefix(images_dir).unwrap_or(path);
+        let name = Path::new(path.file_name().unwrap_or_default());
+        let matches = |set: &GlobSet| set.is_match(relative) || set.is_match(name);
+
+        self.include.as_ref().is_none_or(matches) && !matches(&self.exclude)
+    }
+}
+
+/// Log all the failed `results`, one per path, and return them together.
+fn check_failures<T>(
+    paths: &[PathBuf],
+    results: Vec<Result<T, Box<dyn std::error::Error>>>,
+) -> Result<Vec<T>, Error> {
+    let mut successes = Vec::with_capacity(results.len());
+    let mut failures = Vec::new();
+    for (path, result) in paths.iter().zip(results) {
+        match result {
+            Ok(success) => successes.push(success),
+            Err(e) => {
+                error!(target: LOG_TARGET, "IMG: '{}' failed: {e}", path.display());
+                failures.push((path.clone(), e));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(Error::ImageProcessingFailed(failures));
+    }
+    Ok(successes)
+}
+
+/// Hash the image at `path`, and generate its variants if it has a sidecar or
+/// other [`PacklerConfig::image_formats`].
+fn collect_image(
+    config: &PacklerConfig,
+    incremental: Option<&Incremental>,
+    placeholders: Option<&PlaceholderCache>,
+    optimizer: Option<&ImageOptimizer>,
+    hash_exclude: &HashExclude,
+    path: &Path,
+) -> Result<Vec<(AssetMetadata, ImageOutput)>, CollectError> {
+    let overrides = ImageOverrides::load(path)?.unwrap_or_default();
+    let image_content = std::fs::read(path).map_err(|e| CollectError::Read(path.to_owned(), e))?;
+
+    // An optimized image is not the output of a build without optimization,
+    // and the other way around.
+    let source_hash = match optimizer {
+        Some(_) => seahash::hash(&[b"optimized:".as_slice(), &image_content].concat()),
+        None => seahash::hash(&image_content),
+    };
+
+    // Only the PNG and JPEG images get the configured formats and widths.
+    let source_format = OutputFormat::from_path(path).filter(OutputFormat::is_convertible);
+    let (extra_formats, default_widths): (Vec<OutputFormat>, &[u32]) = match source_format {
+        Some(source) => (
+            config
+                .image_formats
+                .iter()
+                .copied()
+                .filter(|format| *format != source)
+                .collect(),
+            &config.image_widths,
+        ),
+        None => (Vec::new(), &[]),
+    };
+    let converted = !extra_formats.is_empty() || !default_widths.is_empty();
 
-        if let Some(dir) = dest_path
-            .parent() { std::fs::create_dir_all(dir).expect("Could not create final directory") }
+    // The variants of a processed image are always generated again.
+    let sidecar = ImageOverrides::sidecar_path(path);
+    let previous = incremental
+        .filter(|_| !overrides.needs_processing() && !converted)
+        .filter(|incremental| !sidecar.exists() || !incremental.is_changed(&sidecar))
+        .and_then(|incremental| incremental.unchanged(config, path, source_hash))
+        .filter(|previous| config.image_placeholders.is_none() || previous.placeholder.is_some());
+    if let Some(previous) = previous {
+        trace!(target: LOG_TARGET, "IMG: {} did not change. Skip", path.display());
+        return Ok(vec![(previous.clone(), ImageOutput::Keep)]);
+    }
+
+    let optimized = optimizer
+        .map(|optimizer| optimizer.optimize(source_format, &image_content))
+        .transpose()?
+        .flatten();
+    // The optimized image replaces the source.
+    let is_optimized = optimized.is_some();
+    let image_content = optimized.unwrap_or(image_content);
+    let copy = || {
+        if is_optimized {
+            ImageOutput::Write(image_content.clone())
+        } else {
+            ImageOutput::Copy
+        }
+    };
+    let hash = seahash::hash(&image_content);
+
+    let relative_path = path
+        .strip_prefix(&config.assets_source_dir)
+        .map_err(|_| format!("not in '{}'", config.assets_source_dir.display()))
+        .map_err(ImageError::from)?;
+
+    debug!(
+        target: LOG_TARGET,
+        "IMG: {} (relative: {})",
+        path.display(),
+        relative_path.display()
+    );
+
+    let placeholder = config
+        .image_placeholders
+        .zip(placeholders)
+        .and_then(|(kind, cache)| cache.get_or_compute(kind, path, &image_content, hash));
+
+    let asset = |logical_path: PathBuf, hash: u64, content: &[u8]| {
+        let (width, height) = dimensions(&logical_path, content).unzip();
+
+        let processed_relative_path =
+            if overrides.no_hash || hash_exclude.is_excluded(&logical_path) {
+                logical_path.clone()
+            } else {
+                hashed_path(&logical_path, hash)
+            };
+
+        AssetMetadata {
+            source_hash,
+            placeholder: placeholder.clone(),
+            width,
+            height,
+            ..AssetMetadata::new(
+                path,
+                logical_path,
+                processed_relative_path,
+                hash,
+                config.integrity_algorithm.integrity(content),
+            )
+        }
+    };
+
+    let render = |variant: Variant| -> Result<_, CollectError> {
+        debug!(
+            target: LOG_TARGET,
+            "IMG: generating {variant:?} of {}",
+            path.display()
+        );
+        let content = overrides.render(&image_content, variant)?;
+        let logical_path = relative_path.with_file_name(variant.file_name(relative_path));
+        Ok((
+            asset(logical_path, seahash::hash(&content), &content),
+            ImageOutput::Write(content),
+        ))
+    };
+
+    if !overrides.needs_processing() && !converted {
+        return Ok(vec![(
+            asset(relative_path.to_owned(), hash, &image_content),
+            copy(),
+        )]);
+    }
+
+    // Never upscaled: the widths larger than the source are its own size.
+    let source_width = dimensions(path, &image_content).map(|(width, _)| width);
+    let mut fitted = HashSet::new();
+    overrides
+        .variants(path, &extra_formats, default_widths)?
+        .into_iter()
+        .map(|variant| variant.fit(source_width))
+        .filter(|variant| fitted.insert(*variant))
+        .map(|variant| {
+            let original = variant.width.is_none() && Some(variant.format) == source_format;
+            if original && !overrides.needs_processing() {
+                Ok((
+                    asset(relative_path.to_owned(), hash, &image_content),
+                    copy(),
+                ))
+            } else {
+                render(variant)
+            }
+        })
+        .collect()
+}
+
+/// Group the images generated at several widths from the same source, one
+/// group per format, from the narrowest: `images/logo.png` lists
+/// `logo-320w-<hash>.png`, `logo-640w-<hash>.png` and `logo-<hash>.png`.
+///
+/// The key is the logical path of the image at the size of its source.
+pub fn srcsets(images: &[AssetMetadata]) -> BTreeMap<PathBuf, Vec<SrcsetCandidate>> {
+    let mut srcsets: BTreeMap<PathBuf, Vec<SrcsetCandidate>> = BTreeMap::new();
+    for image in images.iter().filter(|image| !image.mutable) {
+        let (Some(width), Some(stem), Some(name), Some(extension)) = (
+            image.width,
+            image.source_path.file_stem(),
+            image.logical_path.file_stem(),
+            image.logical_path.extension(),
+        ) else {
+            continue;
+        };
+        let (stem, name) = (stem.to_string_lossy(), name.to_string_lossy());
+        if name != stem && name != format!("{stem}-{width}w") {
+            continue;
+        }
+
+        let key = image
+            .logical_path
+            .with_file_name(format!("{stem}.{}", extension.to_string_lossy()));
+        srcsets.entry(key).or_default().push(SrcsetCandidate {
+            processed_relative_path: image.processed_relative_path.clone(),
+            width,
+        });
+    }
+
+    srcsets.retain(|_, candidates| candidates.len() > 1);
+    for candidates in srcsets.values_mut() {
+        candidates.sort_by_key(|candidate| candidate.width);
+    }
+    srcsets
+}
+
+/// Copy or write `image` to the dist directory.
+fn write_image(
+    config: &PacklerConfig,
+    image: &AssetMetadata,
+    output: &ImageOutput,
+) -> Result<(), ImageError> {
+    if matches!(output, ImageOutput::Keep) {
+        return Ok(());
+    }
+
+    let dest_path = config.dist_dir.join(&image.processed_relative_path);
+    if config.dry_run {
+        info!(target: LOG_TARGET, "DRY RUN: would write '{}'", dest_path.display());
+        return Ok(());
+    }
 
-        std::fs::copy(&image.source_path, &dest_path).unwrap();
+    if let Some(dir) = dest_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    match output {
+        ImageOutput::Keep => unreachable!(),
+        ImageOutput::Copy => {
+            std::fs::copy(&image.source_path, &dest_path)?;
+        }
+        ImageOutput::Write(content) => std::fs::write(&dest_path, content)?,
+    }
+    common::record_write(&dest_path);
+    Ok(())
+}
+
+/// The width and height of an image. Only the header of raster images is
+/// read, SVGs get the size of their `width`/`height` attributes, or of their
+/// `viewBox`.
+pub fn dimensions(path: &Path, content: &[u8]) -> Option<(u32, u32)> {
+    let is_svg = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    if is_svg {
+        return svg_dimensions(&String::from_utf8_lossy(content));
     }
 
-    Ok(images)
+    image::io::Reader::new(Cursor::new(content))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
 }
 
-pub fn clean_dist_dir(cfg: &PacklerConfig) {
+fn svg_dimensions(svg: &str) -> Option<(u32, u32)> {
+    let start = svg.find("<svg")?;
+    let tag = &svg[start..start + svg[start..].find('>')?];
+
+    let attribute = |name: &str| {
+        let (_, rest) = tag.split_once(&format!(" {name}="))?;
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        rest[1..].split(quote).next()
+    };
+    // Relative sizes (e.g., `100%`) cannot be known.
+    let length = |value: &str| {
+        value
+            .trim()
+            .trim_end_matches("px")
+            .parse::<f64>()
+            .ok()
+            .filter(|length| *length > 0.0)
+            .map(|length| length.round() as u32)
+    };
+
+    let attributes = attribute("width")
+        .and_then(length)
+        .zip(attribute("height").and_then(length));
+    attributes.or_else(|| {
+        let view_box: Vec<&str> = attribute("viewBox")?
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|part| !part.is_empty())
+            .collect();
+        match view_box[..] {
+            [_, _, width, height] => length(width).zip(length(height)),
+            _ => None,
+        }
+    })
+}
+
+pub fn clean_dist_dir(cfg: &PacklerConfig) -> Result<(), Error> {
     let images_dir = cfg.dist_image_dir();
 
-    if images_dir.exists() {
-        std::fs::remove_dir_all(&images_dir)
-            .unwrap_or_else(|_| panic!("Could not remove '{}'", images_dir.display()))
+    if images_dir.exists() && cfg.dry_run {
+        info!(target: LOG_TARGET, "DRY RUN: would remove '{}'", images_dir.display());
+    } else if images_dir.exists() {
+        std::fs::remove_dir_all(&images_dir).map_err(Error::CannotRemoveDistDir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dimensions_of_raster_and_svg_images() {
+        let mut png = Cursor::new(Vec::new());
+        image::RgbImage::new(40, 30)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        assert_eq!(
+            dimensions(Path::new("a.png"), png.get_ref()),
+            Some((40, 30))
+        );
+
+        let svg = |attributes: &str| {
+            let svg = format!(
+                "<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\" {attributes}>"
+            );
+            dimensions(Path::new("a.svg"), svg.as_bytes())
+        };
+        assert_eq!(svg(r#"width="24px" height='12'"#), Some((24, 12)));
+        assert_eq!(svg(r#"viewBox="0 0 100.4 50""#), Some((100, 50)));
+        assert_eq!(
+            svg(r#"width="100%" height="100%" viewBox="0,0,10,20""#),
+            Some((10, 20))
+        );
+        assert_eq!(svg(r#"width="100%""#), None);
+        assert_eq!(dimensions(Path::new("a.png"), b"not an image"), None);
+    }
+
+    #[test]
+    fn all_the_failed_images_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            ..Default::default()
+        };
+        let images = config.source_image_dir();
+        std::fs::create_dir_all(&images).unwrap();
+        for name in ["a.svg", "b.svg", "c.svg"] {
+            std::fs::write(images.join(name), "<svg></svg>").unwrap();
+        }
+        std::fs::write(images.join("b.svg.packler.toml"), "widths = 12").unwrap();
+        std::fs::write(images.join("c.svg.packler.toml"), "unknown = true").unwrap();
+
+        let Err(e) = process(&config, None, None) else {
+            panic!("the invalid sidecars must fail the images");
+        };
+        let e = e.downcast::<Error>().unwrap();
+        let Error::ImageProcessingFailed(mut failures) = *e else {
+            panic!("unexpected error: {e}");
+        };
+        failures.sort_by(|a, b| a.0.cmp(&b.0));
+        let failed: Vec<_> = failures.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(failed, [images.join("b.svg"), images.join("c.svg")]);
+
+        std::fs::remove_file(images.join("b.svg.packler.toml")).unwrap();
+        std::fs::remove_file(images.join("c.svg.packler.toml")).unwrap();
+        std::fs::write(images.join("LICENSE"), "MIT").unwrap();
+        let processed = process(&config, None, None).unwrap();
+        assert_eq!(processed.len(), 4);
+        for image in processed {
+            assert!(config.dist_dir.join(image.processed_relative_path).exists());
+        }
+    }
+
+    #[test]
+    fn images_are_filtered_by_globs() {
+        let images = Path::new("assets/images");
+        let mut config = PacklerConfig::default();
+        let filter = ImageFilter::new(&config).unwrap();
+        assert!(filter.is_included(images, &images.join("photos/a.jpg")));
+        assert!(!filter.is_included(images, &images.join("photos/.DS_Store")));
+        assert!(!filter.is_included(images, &images.join("Thumbs.db")));
+        assert!(!filter.is_included(images, &images.join("logo.svg~")));
+
+        config.image_include = vec!["*.png".to_owned(), "icons/**".to_owned()];
+        let filter = ImageFilter::new(&config).unwrap();
+        assert!(filter.is_included(images, &images.join("a.png")));
+        assert!(filter.is_included(images, &images.join("photos/b.png")));
+        assert!(filter.is_included(images, &images.join("icons/menu.svg")));
+        assert!(!filter.is_included(images, &images.join("logo.svg")));
+        assert!(!filter.is_included(images, &images.join("icons/menu.svg.swp")));
+
+        config.image_exclude = vec!["[".to_owned()];
+        assert!(ImageFilter::new(&config).is_err());
+    }
+
+    #[test]
+    fn raster_images_are_converted_and_resized() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            image_formats: vec![OutputFormat::Webp],
+            image_widths: vec![2, 8],
+            ..Default::default()
+        };
+        let images = config.source_image_dir();
+        std::fs::create_dir_all(&images).unwrap();
+        image::RgbImage::new(4, 2)
+            .save(images.join("hero.png"))
+            .unwrap();
+        std::fs::write(images.join("icon.svg"), "<svg></svg>").unwrap();
+
+        let mut processed = process(&config, None, None).unwrap();
+        processed.sort_by(|a, b| a.logical_path.cmp(&b.logical_path));
+        let logical: Vec<_> = processed.iter().map(|i| i.logical_path.clone()).collect();
+        // Not upscaled to 8px.
+        assert_eq!(
+            logical,
+            [
+                Path::new("images/hero-2w.png"),
+                Path::new("images/hero-2w.webp"),
+                Path::new("images/hero.png"),
+                Path::new("images/hero.webp"),
+                Path::new("images/icon.svg"),
+            ]
+        );
+
+        let webp = &processed[1];
+        assert!(webp
+            .processed_relative_path
+            .to_string_lossy()
+            .ends_with(&format!("hero-2w-{:016x}.webp", webp.hash)));
+        let content = std::fs::read(config.dist_dir.join(&webp.processed_relative_path)).unwrap();
+        assert_eq!(
+            image::guess_format(&content).unwrap(),
+            image::ImageFormat::WebP
+        );
+        assert_eq!((webp.width, webp.height), (Some(2), Some(1)));
+
+        let srcsets = srcsets(&processed);
+        let widths = |key: &str| -> Vec<(PathBuf, u32)> {
+            srcsets[Path::new(key)]
+                .iter()
+                .map(|c| (c.processed_relative_path.clone(), c.width))
+                .collect()
+        };
+        assert_eq!(srcsets.len(), 2);
+        assert_eq!(
+            widths("images/hero.webp"),
+            [
+                (processed[1].processed_relative_path.clone(), 2),
+                (processed[3].processed_relative_path.clone(), 4),
+            ]
+        );
     }
 }
@@ -0,0 +1,245 @@
+//! Incremental builds.
+//!
+//! The metadata of the previous build is reused for the sources that did not
+//! change: their content hash is still the one stored in the metadata file
+//! (see [`AssetMetadata::source_hash`]), and, when building since a git ref,
+//! `git diff` does not list them.
+
+use crate::common;
+use crate::pipelines::assets::{read_metadata_file, AssetMetadata, AssetsOutput, Error};
+use crate::PacklerConfig;
+use log::{debug, info, warn};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+pub struct Incremental {
+    /// Absolute paths of the files that changed since a git ref. Without it,
+    /// only the hashes tell the changes apart.
+    changed: Option<Vec<PathBuf>>,
+
+    /// The metadata of the previous build.
+    previous: AssetsOutput,
+}
+
+impl Incremental {
+    /// Reuse the previous build, if any.
+    pub fn from_previous_build(config: &PacklerConfig) -> Result<Self, Error> {
+        Ok(Self {
+            changed: None,
+            previous: read_metadata_file(config)?,
+        })
+    }
+
+    /// List the files changed since `git_ref` with `git diff --name-only`,
+    /// and the untracked ones, in the git repository of the assets.
+    ///
+    /// Fails if git is not available or if there is no previous build to
+    /// reuse.
+    pub async fn since(
+        git_ref: &str,
+        config: &PacklerConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let previous = read_metadata_file(config)?;
+
+        let git = which::which("git")?;
+        let repo = config.assets_source_dir.display().to_string();
+        let root =
+            common::run_command_output("git", &git, &["-C", &repo, "rev-parse", "--show-toplevel"])
+                .await?;
+        // Like the paths given to `is_changed`, e.g. `/private/var` instead of
+        // `/var` on macOS.
+        let root = PathBuf::from(root.trim()).canonicalize()?;
+
+        let diff =
+            common::run_command_output("git", &git, &["-C", &repo, "diff", "--name-only", git_ref])
+                .await?;
+        let untracked = common::run_command_output(
+            "git",
+            &git,
+            &[
+                "-C",
+                &repo,
+                "ls-files",
+                "--others",
+                "--exclude-standard",
+                "--full-name",
+            ],
+        )
+        .await?;
+        let changed: Vec<PathBuf> = diff
+            .lines()
+            .chain(untracked.lines())
+            .map(|line| root.join(line))
+            .collect();
+
+        info!("{} file(s) changed since '{git_ref}'", changed.len());
+        debug!("Changed files: {changed:?}");
+
+        Ok(Self {
+            changed: Some(changed),
+            previous,
+        })
+    }
+
+    /// Whether `path` changed according to git. Unknown paths are considered
+    /// changed.
+    pub fn is_changed(&self, path: &Path) -> bool {
+        let Some(changed) = &self.changed else {
+            return false;
+        };
+        match path.canonicalize() {
+            Ok(path) => changed.contains(&path),
+            Err(_) => true,
+        }
+    }
+
+    /// The metadata of `source_path` in the previous build, if its processed
+    /// file is still there.
+    pub fn previous(&self, config: &PacklerConfig, source_path: &Path) -> Option<&AssetMetadata> {
+        self.previous.iter().find(|asset| {
+            !asset.mutable
+                && asset.source_path == source_path
+                && config
+                    .dist_dir
+                    .join(&asset.processed_relative_path)
+                    .exists()
+        })
+    }
+
+    /// The metadata of `source_path` in the previous build, if it can be
+    /// reused: the source did not change since, its hash is still
+    /// `source_hash`.
+    pub fn unchanged(
+        &self,
+        config: &PacklerConfig,
+        source_path: &Path,
+        source_hash: u64,
+    ) -> Option<&AssetMetadata> {
+        let integrity_prefix = format!("{}-", config.integrity_algorithm.prefix());
+        self.previous(config, source_path).filter(|previous| {
+            previous.source_hash == source_hash
+                && previous.integrity.starts_with(&integrity_prefix)
+                && !self.is_changed(source_path)
+        })
+    }
+
+    /// Remove the outputs of the previous build that are not in `output`
+    /// anymore, e.g., the previous version of a changed asset.
+    pub fn remove_stale_outputs(&self, config: &PacklerConfig, output: &AssetsOutput) {
+        let outputs: HashSet<&Path> = output.iter().flat_map(AssetMetadata::outputs).collect();
+        for stale in self
+            .previous
+            .iter()
+            .flat_map(AssetMetadata::outputs)
+            .filter(|path| !outputs.contains(path))
+        {
+            let path = config.dist_dir.join(stale);
+            if !path.exists() {
+                continue;
+            }
+
+            if config.dry_run {
+                info!("DRY RUN: would remove '{}'", path.display());
+            } else if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Could not remove '{}': {e}", path.display());
+            } else {
+                debug!("'{}' is not an output anymore, removed", path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PacklerParams, Profile};
+
+    #[tokio::test]
+    async fn unchanged_images_are_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            target: dir.path().join("target"),
+            ..PacklerConfig::default()
+        };
+        let images = config.source_image_dir();
+        std::fs::create_dir_all(&images).unwrap();
+        std::fs::write(images.join("a.svg"), "<svg/>").unwrap();
+        std::fs::write(images.join("b.svg"), "<svg/>").unwrap();
+        let params = PacklerParams::new([""; 0], [""; 0], None);
+        let build =
+            || crate::pipelines::assets::build_assets(&params, &config, None, Profile::Debug);
+
+        let first = build().await.unwrap();
+        let processed = |output: &AssetsOutput, name: &str| {
+            let image = output
+                .images
+                .iter()
+                .find(|image| image.source_path.ends_with(name))
+                .unwrap();
+            config.dist_dir.join(&image.processed_relative_path)
+        };
+        let (a, b) = (processed(&first, "a.svg"), processed(&first, "b.svg"));
+
+        // Would be copied again by a full build.
+        std::fs::write(&a, "kept").unwrap();
+        std::fs::write(images.join("b.svg"), "<svg></svg>").unwrap();
+
+        let second = build().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "kept");
+        assert_eq!(processed(&second, "a.svg"), a);
+        assert_ne!(processed(&second, "b.svg"), b);
+        assert!(processed(&second, "b.svg").exists());
+        assert!(!b.exists());
+    }
+
+    #[tokio::test]
+    async fn changed_and_untracked_files_since_a_git_ref() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=packler",
+                    "-c",
+                    "user.email=packler@example.com",
+                ])
+                .arg("-C")
+                .arg(dir.path())
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            target: dir.path().join("target"),
+            ..PacklerConfig::default()
+        };
+        let images = config.source_image_dir();
+        std::fs::create_dir_all(&images).unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "/dist\n/target\n").unwrap();
+        std::fs::write(images.join("a.svg"), "<svg/>").unwrap();
+        std::fs::write(images.join("b.svg"), "<svg/>").unwrap();
+        git(&["init", "--quiet"]);
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "assets"]);
+        let params = PacklerParams::new([""; 0], [""; 0], None);
+        crate::pipelines::assets::build_assets(&params, &config, None, Profile::Debug)
+            .await
+            .unwrap();
+
+        std::fs::write(images.join("b.svg"), "<svg></svg>").unwrap();
+        std::fs::write(images.join("c.svg"), "<svg/>").unwrap();
+        let incremental = Incremental::since("HEAD", &config).await.unwrap();
+
+        assert!(!incremental.is_changed(&images.join("a.svg")));
+        assert!(incremental.is_changed(&images.join("b.svg")));
+        assert!(incremental.is_changed(&images.join("c.svg")));
+    }
+}
@@ -0,0 +1,64 @@
+//! [Subresource Integrity] digests of the assets, for the `integrity`
+//! attribute of `<link>` and `<script>`.
+//!
+//! The content hash used in the file names is not cryptographic: the digest is
+//! computed separately.
+//!
+//! [Subresource Integrity]: https://developer.mozilla.org/en-US/docs/Web/Security/Subresource_Integrity
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityAlgorithm {
+    Sha256,
+    #[default]
+    Sha384,
+    Sha512,
+}
+
+impl IntegrityAlgorithm {
+    /// The prefix of the integrities computed with this algorithm.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha384 => "sha384",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    /// The integrity of `content`, e.g. `sha384-<base64 digest>`.
+    pub fn integrity(&self, content: &[u8]) -> String {
+        let digest = match self {
+            Self::Sha256 => Sha256::digest(content).to_vec(),
+            Self::Sha384 => Sha384::digest(content).to_vec(),
+            Self::Sha512 => Sha512::digest(content).to_vec(),
+        };
+        let digest = base64::engine::general_purpose::STANDARD.encode(digest);
+        format!("{}-{digest}", self.prefix())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integrity_of_known_content() {
+        // echo -n "alert('Hello, world.');" | openssl dgst -sha384 -binary | openssl base64 -A
+        assert_eq!(
+            IntegrityAlgorithm::Sha384.integrity(b"alert('Hello, world.');"),
+            "sha384-H8BRh8j48O9oYatfu5AZzq6A9RINhZO5H16dQZngK7T62em8MUt1FLm52t+eX6xO"
+        );
+        assert_eq!(
+            IntegrityAlgorithm::Sha256.integrity(b""),
+            "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+        assert_eq!(
+            IntegrityAlgorithm::Sha512.integrity(b"").len(),
+            "sha512-".len() + 88
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! The upload ledger lists the assets already uploaded by a deploy, so an
+//! interrupted deploy can be resumed without uploading them again.
+//!
+//! A ledger only applies to the metadata and the bucket it was written for: its
+//! name contains the bucket name and the hash of the metadata file.
+
+use super::AssetsOutput;
+use crate::PacklerConfig;
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+pub struct UploadLedger {
+    path: PathBuf,
+    uploaded: HashSet<String>,
+    file: File,
+}
+
+/// The path of the ledger for the deploy of `metadata` to `bucket_name`.
+pub fn ledger_path(cfg: &PacklerConfig, metadata: &AssetsOutput, bucket_name: &str) -> PathBuf {
+    let content = serde_json::to_string_pretty(metadata).unwrap_or_default();
+    let hash = seahash::hash(content.as_bytes());
+    cfg.intermediate_dir()
+        .join(format!("deploy-{bucket_name}-{hash:016x}.ledger"))
+}
+
+impl UploadLedger {
+    /// Open the ledger at `path`. Unless `resume` is set, the keys it already
+    /// lists are forgotten.
+    pub fn open(path: PathBuf, resume: bool) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let uploaded = if resume && path.exists() {
+            std::fs::read_to_string(&path)?
+                .lines()
+                .map(str::to_owned)
+                .collect()
+        } else {
+            HashSet::new()
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(&path)?;
+
+        Ok(Self {
+            path,
+            uploaded,
+            file,
+        })
+    }
+
+    /// Whether `key` was uploaded by a previous run.
+    pub fn contains(&self, key: &str) -> bool {
+        self.uploaded.contains(key)
+    }
+
+    pub fn record(&mut self, key: &str) -> std::io::Result<()> {
+        writeln!(self.file, "{key}")?;
+        self.uploaded.insert(key.to_owned());
+        Ok(())
+    }
+
+    /// Remove the ledger, once the deploy is complete.
+    pub fn clear(self) -> std::io::Result<()> {
+        std::fs::remove_file(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_keeps_recorded_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deploy.ledger");
+
+        let mut ledger = UploadLedger::open(path.clone(), false).unwrap();
+        ledger.record("css/app-0123.css").unwrap();
+        drop(ledger);
+
+        let mut ledger = UploadLedger::open(path.clone(), true).unwrap();
+        assert!(ledger.contains("css/app-0123.css"));
+        ledger.record("images/logo-4567.png").unwrap();
+        drop(ledger);
+
+        let ledger = UploadLedger::open(path.clone(), true).unwrap();
+        assert!(ledger.contains("images/logo-4567.png"));
+        drop(ledger);
+
+        let ledger = UploadLedger::open(path.clone(), false).unwrap();
+        assert!(!ledger.contains("css/app-0123.css"));
+        ledger.clear().unwrap();
+        assert!(!path.exists());
+    }
+}
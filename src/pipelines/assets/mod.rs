@@ -1,105 +1,472 @@
-use crate::{pipelines::assets::bucket::AssetBucket, PacklerConfig, PacklerParams};
-use log::{error, info, warn};
+use crate::{
+    common,
+    hooks::Hook,
+    pipelines::assets::{
+        compress::Encoding, critical::CriticalCssMetadata, images::SrcsetCandidate, order::Pipeline,
+    },
+    PacklerConfig, PacklerParams, Profile,
+};
+use globset::GlobSet;
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::Write, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+#[cfg(feature = "deploy")]
 pub mod bucket;
+pub mod compress;
+pub mod copy;
+pub mod critical;
+#[cfg(feature = "deploy")]
+mod deploy;
 pub mod images;
+pub mod incremental;
+pub mod integrity;
+pub mod ledger;
+pub mod optimize;
+pub mod order;
+pub mod overrides;
+pub mod placeholder;
+pub mod rust_metadata;
 pub mod sass;
+pub mod tailwind;
+mod tasks;
 
-pub async fn deploy_assets(params: &PacklerParams, cfg: &PacklerConfig) {
-    info!("building assets");
-    let Ok(metadata) = build_assets_inner(params, cfg).await else {
-        error!("Could not build assets.");
-        return
-    };
+#[cfg(not(feature = "async"))]
+pub(crate) use tasks::build_assets;
+#[cfg(feature = "async")]
+pub use tasks::{build_assets, build_assets_inner};
 
-    info!("uploading assets");
-    let Some(bucket_params) = &params.assets_bucket else {
-        error!("Cannot deploy assets: bucket parameters were not provided");
-        return;
-    };
+#[cfg(all(feature = "deploy", feature = "async"))]
+pub use deploy::{deploy_assets, deploy_files, prune_assets, verify_assets};
+#[cfg(all(feature = "deploy", not(feature = "async")))]
+pub(crate) use deploy::{deploy_assets, deploy_files, prune_assets, verify_assets};
+#[cfg(feature = "deploy")]
+pub use deploy::{
+    deploy_assets_blocking, deploy_files_blocking, prune_assets_blocking, verify_assets_blocking,
+};
 
-    let bucket = AssetBucket::new(bucket_params).await;
-    bucket.send_assets(&cfg, &metadata).await;
-
-    info!("writing metadata file");
-    write_metadata_file(cfg, &metadata);
+/// Build the assets. With `since` (a git ref), only the assets that changed
+/// since this ref are processed again.
+///
+/// Blocks on its own runtime: it panics if called from within one, use
+/// `build_assets` (`async` feature) there.
+pub fn build_assets_blocking(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    since: Option<&str>,
+    profile: Profile,
+) -> Result<AssetsOutput, Error> {
+    common::block_on(build_assets(params, cfg, since, profile))
+}
 
-    info!("setting CORS config on assets bucket");
-    bucket.send_cors().await;
+/// Read back the metadata file written by the last build.
+pub fn read_metadata_file(config: &PacklerConfig) -> Result<AssetsOutput, Error> {
+    config.load_metadata()
 }
 
-pub fn write_metadata_file(config: &PacklerConfig, metadata: &AssetsOutput) {
-    let content = serde_json::to_string_pretty(&metadata)
-        .map_err(Error::CannotSerializeMetadataFile)
-        .unwrap();
+pub fn write_metadata_file(config: &PacklerConfig, metadata: &AssetsOutput) -> Result<(), Error> {
+    if config.dry_run {
+        info!(
+            "DRY RUN: would write the metadata file '{}' ({} assets)",
+            config.metadata_file().display(),
+            metadata.iter().count()
+        );
+        return Ok(());
+    }
+
+    let content = serde_json::to_string_pretty(&MetadataFile {
+        output: metadata,
+        by_logical_path: metadata.by_logical_path(),
+    })
+    .map_err(Error::CannotSerializeMetadataFile)?;
 
     let out_path = config.metadata_file();
 
+    // The pipelines only create the directories they write in: on a fresh
+    // checkout, the dist directory itself may not exist.
+    std::fs::create_dir_all(&config.dist_dir).map_err(Error::CannotWriteMetadataFile)?;
+
     if out_path.exists() {
-        std::fs::remove_file(&out_path).unwrap();
+        std::fs::remove_file(&out_path).map_err(Error::CannotWriteMetadataFile)?;
     }
 
-    File::create(out_path)
+    File::create(&out_path)
         .and_then(|mut f| f.write_all(content.as_bytes()))
-        .map_err(Error::CannotWriteMetadataFile)
-        .unwrap()
+        .map_err(Error::CannotWriteMetadataFile)?;
+    common::record_write(&out_path);
+
+    rust_metadata::write(config, metadata)
+}
+
+/// The content of the metadata file: the [`AssetsOutput`], and the processed
+/// path of each asset by logical path, so a server can look an asset up
+/// without scanning the lists. The map is ignored when the file is read back.
+#[derive(Serialize)]
+struct MetadataFile<'a> {
+    #[serde(flatten)]
+    output: &'a AssetsOutput,
+    by_logical_path: BTreeMap<&'a Path, &'a Path>,
+}
+
+/// Write a new reload token, see [`PacklerConfig::watch_reload_token`].
+///
+/// The token is the current time in milliseconds, and always greater than the
+/// previous one even if the clock goes backward.
+pub fn write_reload_token(cfg: &PacklerConfig) {
+    if cfg.dry_run {
+        return;
+    }
+
+    let path = cfg.reload_token_file();
+    let previous = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|token| token.trim().parse::<u128>().ok());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let token = previous.map_or(now, |previous| now.max(previous + 1));
+
+    let written = std::fs::create_dir_all(&cfg.dist_dir)
+        .and_then(|()| std::fs::write(&path, token.to_string()));
+    match written {
+        Ok(()) => {
+            common::record_write(&path);
+            debug!("reload token {token} written to {path:?}")
+        }
+        Err(e) => warn!("Could not write the reload token to {path:?}: {e}"),
+    }
 }
 
-pub fn clean_assets(cfg: &PacklerConfig) {
-    images::clean_dist_dir(cfg);
-    sass::clean_dist_dir(cfg);
+/// Remove the outputs of the previous builds, with their metadata file.
+pub fn clean_assets(cfg: &PacklerConfig) -> Result<(), Error> {
+    images::clean_dist_dir(cfg)?;
+    sass::clean_dist_dir(cfg)?;
+    copy::clean_dist_dir(cfg)?;
+
+    let metadata_file = cfg.metadata_file();
+    if metadata_file.exists() && cfg.dry_run {
+        info!("DRY RUN: would remove '{}'", metadata_file.display());
+    } else if metadata_file.exists() {
+        std::fs::remove_file(&metadata_file).map_err(Error::CannotRemoveMetadataFile)?;
+    }
+    Ok(())
 }
 
-pub async fn build_assets(params: &PacklerParams, cfg: &PacklerConfig) {
-    info!("building assets");
-    let Ok(metadata) = build_assets_inner(params, cfg).await else {
-        error!("Could not build assets.");
-        return
+/// Remove the whole dist directory. It must be inside the cargo workspace (and
+/// not the workspace itself), it is refused otherwise.
+pub fn remove_dist_dir(cfg: &PacklerConfig) -> Result<(), Error> {
+    // Canonicalized: no symlink nor `..` can lead outside the workspace.
+    let Ok(dist_dir) = cfg.dist_dir.canonicalize() else {
+        debug!("'{}' does not exist", cfg.dist_dir.display());
+        return Ok(());
     };
+    let workspace = crate::cargo_metadata().workspace_root.as_std_path();
+    check_removable(&dist_dir, workspace)?;
 
-    info!("writing metadata file");
-    write_metadata_file(cfg, &metadata);
+    if cfg.dry_run {
+        info!("DRY RUN: would remove '{}'", dist_dir.display());
+        return Ok(());
+    }
+    std::fs::remove_dir_all(&dist_dir).map_err(Error::CannotRemoveDistDir)
 }
 
-pub async fn build_assets_inner(
-    params: &PacklerParams,
-    cfg: &PacklerConfig,
-) -> Result<AssetsOutput, Error> {
-    let processed_images = match images::process(cfg) {
-        Ok(images) => images,
-        Err(e) => {
-            warn!("Could not process images: {e}");
-            Vec::default()
-        }
+fn check_removable(dist_dir: &Path, workspace: &Path) -> Result<(), Error> {
+    let workspace = workspace
+        .canonicalize()
+        .unwrap_or_else(|_| workspace.to_owned());
+    if dist_dir == workspace || !dist_dir.starts_with(&workspace) {
+        return Err(Error::DistDirOutsideWorkspace(dist_dir.to_owned()));
+    }
+    Ok(())
+}
+
+/// Copy each hashed asset to its unhashed name (`app-<hash>.css` to
+/// `app.css`), and list these copies in the metadata as well. See
+/// [`PacklerConfig::emit_unhashed_alias`].
+fn add_unhashed_aliases(cfg: &PacklerConfig, output: &mut AssetsOutput) {
+    for assets in [
+        &mut output.images,
+        &mut output.sass,
+        &mut output.tailwind,
+        &mut output.other,
+    ] {
+        let aliases: Vec<AssetMetadata> = assets
+            .iter()
+            .filter(|asset| !asset.mutable)
+            .filter_map(|asset| {
+                let alias = unhashed_path(&asset.processed_relative_path)?;
+                let (src, dest) = (
+                    cfg.dist_dir.join(&asset.processed_relative_path),
+                    cfg.dist_dir.join(&alias),
+                );
+
+                if cfg.dry_run {
+                    info!(
+                        "DRY RUN: would copy '{}' to '{}'",
+                        src.display(),
+                        dest.display()
+                    );
+                } else if let Err(e) = std::fs::copy(&src, &dest) {
+                    warn!(
+                        "Could not copy '{}' to '{}': {e}",
+                        src.display(),
+                        dest.display()
+                    );
+                    return None;
+                } else {
+                    common::record_write(&dest);
+                }
+
+                Some(AssetMetadata {
+                    processed_relative_path: alias,
+                    variants: Vec::new(),
+                    source_map: None,
+                    mutable: true,
+                    ..asset.clone()
+                })
+            })
+            .collect();
+        assets.extend(aliases);
+    }
+}
+
+/// Set the `public_url` of all the assets, see
+/// [`PacklerConfig::public_base_url`].
+fn set_public_urls(cfg: &PacklerConfig, output: &mut AssetsOutput) {
+    let assets = output
+        .images
+        .iter_mut()
+        .chain(output.sass.iter_mut())
+        .chain(output.tailwind.iter_mut())
+        .chain(output.other.iter_mut())
+        .chain(
+            output
+                .critical_css
+                .iter_mut()
+                .map(|critical| &mut critical.asset),
+        );
+    for asset in assets {
+        asset.public_url = cfg.public_base_url.as_deref().map(|base_url| {
+            let path = common::to_slash(&asset.processed_relative_path);
+            format!("{}/{path}", base_url.trim_end_matches('/'))
+        });
+    }
+}
+
+/// Write the compressed variants of the CSS assets, see
+/// [`PacklerConfig::precompress`].
+fn precompress_text_assets(cfg: &PacklerConfig, output: &mut AssetsOutput) -> Result<(), Error> {
+    let critical = output
+        .critical_css
+        .as_mut()
+        .map(|critical| &mut critical.asset);
+    for asset in output
+        .sass
+        .iter_mut()
+        .chain(output.tailwind.iter_mut())
+        .chain(critical)
+        .filter(|asset| !asset.mutable)
+    {
+        asset.variants = compress::precompress(cfg, &asset.processed_relative_path)
+            .map_err(|e| Error::CannotPrecompress(asset.processed_relative_path.clone(), e))?;
+    }
+    Ok(())
+}
+
+/// `css/app.css` with the `hash` before its extension:
+/// `css/app-0123456789abcdef.css`.
+///
+/// Only the last extension is kept after the hash, no part of the name is
+/// lost: `foo.min.css` is `foo.min-<hash>.css`, and a file without an
+/// extension (e.g., `LICENSE`) is `LICENSE-<hash>`.
+fn hashed_path(path: &Path, hash: u64) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}-{hash:016x}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{hash:016x}"),
     };
+    path.with_file_name(file_name)
+}
+
+/// The assets that keep their name, see [`PacklerConfig::hash_exclude`].
+pub struct HashExclude(GlobSet);
 
-    let processed_sass = match sass::process(cfg, &params.sass_entrypoints).await {
-        Ok(sass) => sass,
-        Err(e) => {
-            warn!("Could not process SASS files: {e}");
-            Vec::default()
+impl HashExclude {
+    pub fn new(cfg: &PacklerConfig) -> Result<Self, crate::config::Error> {
+        cfg.hash_exclude_set().map(Self)
+    }
+
+    /// Whether the asset at `logical_path` keeps its name.
+    pub fn is_excluded(&self, logical_path: &Path) -> bool {
+        let name = Path::new(logical_path.file_name().unwrap_or_default());
+        self.0.is_match(logical_path) || self.0.is_match(name)
+    }
+
+    /// `logical_path` with the `hash`, unless it keeps its name.
+    pub fn processed_path(&self, logical_path: &Path, hash: u64) -> PathBuf {
+        if self.is_excluded(logical_path) {
+            logical_path.to_owned()
+        } else {
+            hashed_path(logical_path, hash)
         }
-    };
+    }
+}
 
-    let output = AssetsOutput {
-        images: processed_images,
-        sass: processed_sass,
+/// `css/app-0123456789abcdef.css` without its hash, `None` if it has none.
+fn unhashed_path(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let (name, hash) = stem.rsplit_once('-')?;
+    let is_hash = hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit());
+    let file_name = match path.extension() {
+        Some(extension) => format!("{name}.{}", extension.to_string_lossy()),
+        None => name.to_owned(),
     };
+    is_hash.then(|| path.with_file_name(file_name))
+}
+
+/// The configured build id, or the current git commit.
+async fn resolve_build_id(cfg: &PacklerConfig) -> Option<String> {
+    if let Some(build_id) = &cfg.build_id {
+        return Some(build_id.clone());
+    }
 
-    Ok(output)
+    // Not being in a git repository is fine: no error on the console.
+    let output = tokio::process::Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .await;
+    match output {
+        Ok(output) if output.status.success() => {
+            let sha = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+            Some(sha).filter(|sha| !sha.is_empty())
+        }
+        _ => {
+            debug!("no git commit found, the metadata file has no build id");
+            None
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+/// The version of the schema of the metadata file, bumped on incompatible
+/// changes. A metadata file of a later version cannot be read.
+pub const METADATA_VERSION: u32 = 1;
+
+#[derive(Default, Serialize, Deserialize)]
 pub struct AssetsOutput {
+    /// The schema version of the metadata file this output was read from, see
+    /// [`METADATA_VERSION`]. Always written as [`METADATA_VERSION`]; the files
+    /// written before it was added are version 1.
+    #[serde(
+        default = "first_version",
+        serialize_with = "serialize_version",
+        deserialize_with = "deserialize_version"
+    )]
+    pub version: u32,
+
+    /// See [`PacklerConfig::build_id`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+
     pub images: Vec<AssetMetadata>,
+
+    /// The images generated at several widths, see [`images::srcsets`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub srcsets: BTreeMap<PathBuf, Vec<SrcsetCandidate>>,
+
     pub sass: Vec<AssetMetadata>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tailwind: Vec<AssetMetadata>,
+
+    /// The files of the [`PacklerConfig::copy_dirs`], see [`copy`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other: Vec<AssetMetadata>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub critical_css: Option<CriticalCssMetadata>,
+}
+
+impl AssetMetadata {
+    /// An asset without dependencies, variants, source map nor image
+    /// attributes. Set them with the struct update syntax.
+    pub fn new<S: Into<PathBuf>, L: Into<PathBuf>, P: Into<PathBuf>>(
+        source_path: S,
+        logical_path: L,
+        processed_relative_path: P,
+        hash: u64,
+        integrity: String,
+    ) -> Self {
+        Self {
+            source_path: source_path.into(),
+            logical_path: logical_path.into(),
+            processed_relative_path: processed_relative_path.into(),
+            hash,
+            source_hash: 0,
+            dependencies: Vec::new(),
+            integrity,
+            variants: Vec::new(),
+            source_map: None,
+            placeholder: None,
+            width: None,
+            height: None,
+            mutable: false,
+            public_url: None,
+        }
+    }
+
+    /// The files of the asset in the dist directory: the processed file, its
+    /// variants and its source map.
+    pub fn outputs(&self) -> impl Iterator<Item = &'_ Path> {
+        std::iter::once(self.processed_relative_path.as_path())
+            .chain(self.variants.iter().map(|(_, path)| path.as_path()))
+            .chain(self.source_map.as_deref())
+    }
 }
 
 impl AssetsOutput {
+    /// Read a metadata file written by [`write_metadata_file`], e.g., copied
+    /// next to a web server.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let content =
+            std::fs::read_to_string(path.as_ref()).map_err(Error::CannotReadMetadataFile)?;
+        serde_json::from_str(&content).map_err(Error::CannotParseMetadataFile)
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &'_ AssetMetadata> {
-        self.images.iter().chain(self.sass.iter())
+        self.images
+            .iter()
+            .chain(self.sass.iter())
+            .chain(self.tailwind.iter())
+            .chain(self.other.iter())
+            .chain(self.critical_css.iter().map(|critical| &critical.asset))
+    }
+
+    /// The asset built from `logical_path` (e.g., `css/app.scss`), never its
+    /// unhashed alias.
+    pub fn get(&self, logical_path: impl AsRef<Path>) -> Option<&AssetMetadata> {
+        let logical_path = logical_path.as_ref();
+        self.iter()
+            .find(|asset| !asset.mutable && asset.logical_path == logical_path)
+    }
+
+    /// The processed path of each asset, by logical path. Like [`Self::get`],
+    /// the first asset of a logical path wins.
+    pub fn by_logical_path(&self) -> BTreeMap<&Path, &Path> {
+        let mut paths = BTreeMap::new();
+        for asset in self.iter().filter(|asset| !asset.mutable) {
+            paths
+                .entry(asset.logical_path.as_path())
+                .or_insert(asset.processed_relative_path.as_path());
+        }
+        paths
     }
 }
 
@@ -109,15 +476,144 @@ pub struct AssetMetadata {
     pub logical_path: PathBuf,
     pub processed_relative_path: PathBuf,
 
-    #[serde(skip)]
+    /// The hash of the content, in hex like in the hashed file names. `0` in
+    /// the metadata files written before it was serialized.
+    #[serde(
+        default,
+        serialize_with = "serialize_hash",
+        deserialize_with = "deserialize_hash"
+    )]
     pub hash: u64,
+
+    /// The hash of the sources the content is generated from: an unchanged
+    /// source is not processed again, see [`Incremental`]. `0` when unknown.
+    #[serde(
+        default,
+        serialize_with = "serialize_hash",
+        deserialize_with = "deserialize_hash"
+    )]
+    pub source_hash: u64,
+
+    /// The files the source imports, directly or not, e.g., the partials of a
+    /// SASS entrypoint. See [`sass::affected_entrypoints`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<PathBuf>,
+
+    /// The Subresource Integrity of the content, e.g. `sha384-<base64>`, see
+    /// [`PacklerConfig::integrity_algorithm`].
+    #[serde(default)]
+    pub integrity: String,
+
+    /// The pre-compressed variants of the content, relative to the dist
+    /// directory. See [`PacklerConfig::precompress`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<(Encoding, PathBuf)>,
+
+    /// The source map of the CSS, relative to the dist directory. See
+    /// [`PacklerConfig::sass_source_maps`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_map: Option<PathBuf>,
+
+    /// A tiny placeholder of the image, see
+    /// [`PacklerConfig::image_placeholders`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placeholder: Option<String>,
+
+    /// The size of the image in pixels, when it can be determined. Useful to
+    /// set the `width`/`height` attributes of an `<img>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+
+    /// The content at [`Self::processed_relative_path`] may change from one
+    /// build to the other, unlike the hashed ones: it must not be cached
+    /// forever. See [`PacklerConfig::emit_unhashed_alias`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mutable: bool,
+
+    /// The URL of [`Self::processed_relative_path`], with
+    /// [`PacklerConfig::public_base_url`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_url: Option<String>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+fn serialize_version<S: serde::Serializer>(
+    _version: &u32,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u32(METADATA_VERSION)
+}
+
+/// Refuse the files written by a later Packler: they could be misread.
+fn deserialize_version<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let version = u32::deserialize(deserializer)?;
+    if version > METADATA_VERSION {
+        return Err(serde::de::Error::custom(format!(
+            "unsupported metadata version {version}, this version of Packler reads up to \
+             version {METADATA_VERSION}"
+        )));
+    }
+    Ok(version)
+}
+
+fn serialize_hash<S: serde::Serializer>(hash: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{hash:016x}"))
+}
+
+fn deserialize_hash<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let hash = String::deserialize(deserializer)?;
+    u64::from_str_radix(&hash, 16).map_err(serde::de::Error::custom)
 }
 
 #[derive(Debug)]
 pub enum Error {
     EntryPointDoesNotExist(String),
+    InvalidEntryPointGlob(globset::Error),
+    EntryPointGlobMatchesNothing(String),
+    /// The [`PacklerConfig::sass_binary`] does not exist or cannot be run.
+    SassBinaryNotExecutable(PathBuf),
     CannotSerializeMetadataFile(serde_json::Error),
     CannotWriteMetadataFile(std::io::Error),
+    CannotReadMetadataFile(std::io::Error),
+    CannotRemoveMetadataFile(std::io::Error),
+    CannotReadFile(std::io::Error),
+    ImageReadFailed(PathBuf, std::io::Error),
+    NotInDistDir(PathBuf),
+    DistDirOutsideWorkspace(PathBuf),
+    CannotCreateDistDir(std::io::Error),
+    CannotRemoveDistDir(std::io::Error),
+    CannotPrecompress(PathBuf, std::io::Error),
+    CannotParseMetadataFile(serde_json::Error),
+    MissingBucketParams,
+    #[cfg(feature = "deploy")]
+    UnknownStorageClass(String),
+    DeployVerificationFailed(usize),
+    HookFailed(Hook, anyhow::Error),
+    PipelineFailed(Pipeline, Box<dyn std::error::Error>),
+    /// The SASS entrypoints that could not be compiled, with the reason.
+    SassCompilationFailed(Vec<(PathBuf, Box<dyn std::error::Error>)>),
+    /// The Tailwind entrypoints that could not be compiled, with the reason.
+    TailwindCompilationFailed(Vec<(PathBuf, Box<dyn std::error::Error>)>),
+    /// The images that could not be processed, with the reason.
+    ImageProcessingFailed(Vec<(PathBuf, Box<dyn std::error::Error>)>),
+    /// The keys of the objects that could not be uploaded, with the reason.
+    CannotUploadAssets(Vec<(String, String)>),
+    CannotUploadMetadataFile(Box<dyn std::error::Error>),
+    CannotPruneBucket(Box<dyn std::error::Error>),
+    /// With [`PacklerParams::mirror_buckets`], the buckets whose deploy failed,
+    /// with the reason.
+    #[cfg(feature = "deploy")]
+    BucketsFailed(Vec<(String, Error)>),
+    DeployTimedOut {
+        timeout: Duration,
+        uploaded: usize,
+    },
 }
 
 impl std::error::Error for Error {
@@ -125,6 +621,19 @@ impl std::error::Error for Error {
         match self {
             Self::CannotSerializeMetadataFile(source) => Some(source),
             Self::CannotWriteMetadataFile(source) => Some(source),
+            Self::CannotReadMetadataFile(source) => Some(source),
+            Self::CannotRemoveMetadataFile(source) => Some(source),
+            Self::CannotReadFile(source) => Some(source),
+            Self::ImageReadFailed(_, source) => Some(source),
+            Self::CannotCreateDistDir(source) => Some(source),
+            Self::CannotRemoveDistDir(source) => Some(source),
+            Self::CannotPrecompress(_, source) => Some(source),
+            Self::CannotParseMetadataFile(source) => Some(source),
+            Self::InvalidEntryPointGlob(source) => Some(source),
+            Self::HookFailed(_, source) => Some(source.as_ref()),
+            Self::PipelineFailed(_, source) => Some(source.as_ref()),
+            Self::CannotUploadMetadataFile(source) => Some(source.as_ref()),
+            Self::CannotPruneBucket(source) => Some(source.as_ref()),
             _ => None,
         }
     }
@@ -136,10 +645,268 @@ impl std::fmt::Display for Error {
             Error::EntryPointDoesNotExist(entrypoint) => {
                 write!(f, "Entrypoint '{entrypoint}' does not exist")
             }
+            Error::InvalidEntryPointGlob(source) => {
+                write!(f, "Invalid entrypoint glob: '{source}'")
+            }
+            Error::EntryPointGlobMatchesNothing(glob) => {
+                write!(f, "Entrypoint glob '{glob}' does not match any file")
+            }
+            Error::SassBinaryNotExecutable(path) => write!(
+                f,
+                "The sass binary '{}' does not exist or is not executable",
+                path.display()
+            ),
             Error::CannotSerializeMetadataFile(source) => {
                 write!(f, "Could not serialize json metadata output: '{source}'")
             }
             Error::CannotWriteMetadataFile(source) => write!(f, "Cannot write file: '{source}'"),
+            Error::CannotReadMetadataFile(source) => {
+                write!(f, "Cannot read metadata file: '{source}'")
+            }
+            Error::CannotRemoveMetadataFile(source) => {
+                write!(f, "Cannot remove metadata file: '{source}'")
+            }
+            Error::CannotReadFile(source) => write!(f, "Cannot read file: '{source}'"),
+            Error::ImageReadFailed(path, source) => {
+                write!(f, "Cannot read the image '{}': '{source}'", path.display())
+            }
+            Error::NotInDistDir(path) => {
+                write!(
+                    f,
+                    "'{}' is not a file of the dist directory",
+                    path.display()
+                )
+            }
+            Error::DistDirOutsideWorkspace(path) => write!(
+                f,
+                "Refusing to remove '{}': it is not inside the workspace",
+                path.display()
+            ),
+            Error::CannotCreateDistDir(source) => {
+                write!(f, "Cannot create the dist directory: '{source}'")
+            }
+            Error::CannotRemoveDistDir(source) => {
+                write!(f, "Cannot remove the dist directory: '{source}'")
+            }
+            Error::CannotPrecompress(path, source) => {
+                write!(f, "Cannot compress '{}': '{source}'", path.display())
+            }
+            Error::CannotParseMetadataFile(source) => {
+                write!(f, "Could not parse json metadata file: '{source}'")
+            }
+            Error::MissingBucketParams => write!(f, "Bucket parameters were not provided"),
+            #[cfg(feature = "deploy")]
+            Error::UnknownStorageClass(value) => write!(
+                f,
+                "Unknown storage class '{value}', expected one of: {}",
+                aws_sdk_s3::types::StorageClass::values().join(", ")
+            ),
+            Error::DeployVerificationFailed(count) => {
+                write!(f, "Deploy verification found {count} problem(s)")
+            }
+            Error::HookFailed(hook, source) => write!(f, "The {hook} hook failed: '{source}'"),
+            Error::PipelineFailed(pipeline, source) => {
+                write!(f, "The {pipeline} pipeline failed: '{source}'")
+            }
+            Error::SassCompilationFailed(failures) => {
+                write!(f, "{} SASS entrypoint(s) failed to compile", failures.len())?;
+                for (entrypoint, source) in failures {
+                    write!(f, "\n- '{}': {source}", entrypoint.display())?;
+                }
+                Ok(())
+            }
+            Error::TailwindCompilationFailed(failures) => {
+                write!(
+                    f,
+                    "{} Tailwind entrypoint(s) failed to compile",
+                    failures.len()
+                )?;
+                for (entrypoint, source) in failures {
+                    write!(f, "\n- '{}': {source}", entrypoint.display())?;
+                }
+                Ok(())
+            }
+            Error::ImageProcessingFailed(failures) => {
+                write!(f, "{} image(s) could not be processed", failures.len())?;
+                for (image, source) in failures {
+                    write!(f, "\n- '{}': {source}", image.display())?;
+                }
+                Ok(())
+            }
+            Error::CannotUploadAssets(failures) => {
+                write!(f, "{} asset(s) could not be uploaded", failures.len())?;
+                for (key, reason) in failures {
+                    write!(f, "\n- '{key}': {reason}")?;
+                }
+                Ok(())
+            }
+            Error::CannotUploadMetadataFile(source) => {
+                write!(f, "Could not upload the metadata file: '{source}'")
+            }
+            Error::CannotPruneBucket(source) => {
+                write!(f, "Could not prune the stale objects: '{source}'")
+            }
+            #[cfg(feature = "deploy")]
+            Error::BucketsFailed(failures) => {
+                write!(f, "{} bucket(s) failed", failures.len())?;
+                for (bucket, source) in failures {
+                    write!(f, "\n- '{bucket}': {source}")?;
+                }
+                Ok(())
+            }
+            Error::DeployTimedOut { timeout, uploaded } => write!(
+                f,
+                "Deploy timed out after {}s, {uploaded} object(s) were uploaded",
+                timeout.as_secs()
+            ),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dist_dir_must_be_inside_the_workspace() {
+        let workspace = tempfile::tempdir().unwrap();
+        let workspace = workspace.path().canonicalize().unwrap();
+
+        assert!(check_removable(&workspace.join("dist"), &workspace).is_ok());
+        assert!(matches!(
+            check_removable(&workspace, &workspace),
+            Err(Error::DistDirOutsideWorkspace(_))
+        ));
+        assert!(matches!(
+            check_removable(workspace.parent().unwrap(), &workspace),
+            Err(Error::DistDirOutsideWorkspace(_))
+        ));
+    }
+
+    #[test]
+    fn hashed_and_unhashed_paths() {
+        let hash = 0x0123456789abcdef;
+        for (path, hashed) in [
+            ("css/foo.min.css", "css/foo.min-0123456789abcdef.css"),
+            (
+                "images/archive.tar.gz",
+                "images/archive.tar-0123456789abcdef.gz",
+            ),
+            ("images/LICENSE", "images/LICENSE-0123456789abcdef"),
+            ("images/.htaccess", "images/.htaccess-0123456789abcdef"),
+        ] {
+            assert_eq!(hashed_path(Path::new(path), hash), Path::new(hashed));
+            assert_eq!(unhashed_path(Path::new(hashed)).unwrap(), Path::new(path));
+        }
+
+        assert_eq!(
+            unhashed_path(Path::new("css/app-0123456789abcdef.css")),
+            Some(PathBuf::from("css/app.css"))
+        );
+        assert_eq!(
+            unhashed_path(Path::new("images/a.b-0123456789abcdef.png")),
+            Some(PathBuf::from("images/a.b.png"))
+        );
+        assert_eq!(unhashed_path(Path::new("images/hero-640w.png")), None);
+    }
+
+    #[test]
+    fn later_metadata_versions_are_refused() {
+        let metadata: AssetsOutput = serde_json::from_str(r#"{"images": [], "sass": []}"#).unwrap();
+        assert_eq!(metadata.version, 1);
+        let json = serde_json::to_value(AssetsOutput::default()).unwrap();
+        assert_eq!(json["version"], METADATA_VERSION);
+
+        let json = format!(
+            r#"{{"version": {}, "images": [], "sass": []}}"#,
+            METADATA_VERSION + 1
+        );
+        let error = serde_json::from_str::<AssetsOutput>(&json).err().unwrap();
+        assert!(error.to_string().contains("unsupported metadata version"));
+    }
+
+    #[test]
+    fn hash_is_serialized_in_hex() {
+        let json = r#"{
+            "source_path": "assets/css/app.scss",
+            "logical_path": "css/app.scss",
+            "processed_relative_path": "css/app-00000000000000ff.css"
+        }"#;
+        let mut metadata: AssetMetadata = serde_json::from_str(json).unwrap();
+        assert_eq!(metadata.hash, 0);
+
+        metadata.hash = 255;
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(json["hash"], "00000000000000ff");
+        let metadata: AssetMetadata = serde_json::from_value(json).unwrap();
+        assert_eq!(metadata.hash, 255);
+    }
+
+    #[tokio::test]
+    async fn build_into_a_missing_dist_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist").join("public"),
+            target: dir.path().join("target"),
+            public_base_url: Some("https://cdn.example.com/".to_owned()),
+            ..PacklerConfig::default()
+        };
+        std::fs::create_dir_all(config.source_image_dir()).unwrap();
+        std::fs::write(config.source_image_dir().join("logo.svg"), "<svg/>").unwrap();
+        let params = PacklerParams::new([""; 0], [""; 0], None);
+
+        build_assets(&params, &config, None, Profile::Debug)
+            .await
+            .unwrap();
+
+        let metadata = config.load_metadata().unwrap();
+        assert_eq!(metadata.images.len(), 1);
+        let logo = metadata.get("images/logo.svg").unwrap();
+        assert!(config.dist_dir.join(&logo.processed_relative_path).exists());
+
+        let json = std::fs::read_to_string(config.metadata_file()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            json["by_logical_path"]["images/logo.svg"],
+            logo.processed_relative_path.to_str().unwrap()
+        );
+        assert!(metadata.get("images/missing.svg").is_none());
+        assert_eq!(
+            logo.public_url,
+            Some(format!(
+                "https://cdn.example.com/{}",
+                common::to_slash(&logo.processed_relative_path)
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_build_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            target: dir.path().join("target"),
+            // Neither run nor downloaded.
+            sass_binary: Some(dir.path().join("missing-sass")),
+            image_placeholders: Some(placeholder::PlaceholderKind::Blurhash),
+            dry_run: true,
+            ..PacklerConfig::default()
+        };
+        std::fs::create_dir_all(config.source_sass_dir()).unwrap();
+        std::fs::write(config.source_sass_dir().join("main.scss"), "a {}").unwrap();
+        std::fs::create_dir_all(config.source_image_dir()).unwrap();
+        std::fs::write(config.source_image_dir().join("logo.svg"), "<svg/>").unwrap();
+        let params = PacklerParams::new(["main.scss"], [""; 0], None);
+
+        let _lock = crate::lock::BuildLock::acquire(&config).await.unwrap();
+        build_assets(&params, &config, None, Profile::Debug)
+            .await
+            .unwrap();
+        clean_assets(&config).unwrap();
+
+        assert!(!config.dist_dir.exists());
+        assert!(!config.target.exists());
+    }
+}
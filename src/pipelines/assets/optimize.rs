@@ -0,0 +1,165 @@
+//! Lossless optimization of the source images, see
+//! [`PacklerConfig::optimize_images`].
+//!
+//! The PNG images go through [oxipng](https://github.com/shssoichiro/oxipng)
+//! (downloaded if needed) and the JPEG images through `jpegtran` (installed on
+//! the system), both strip the metadata (e.g., EXIF). The other formats are
+//! copied as is.
+
+use crate::pipelines::assets::{images::LOG_TARGET, overrides::OutputFormat};
+use crate::tools::{self, Application};
+use crate::PacklerConfig;
+use log::{info, warn};
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+/// The images are processed in parallel: the errors must be `Send`.
+type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+pub struct ImageOptimizer {
+    oxipng: Option<PathBuf>,
+    jpegtran: Option<PathBuf>,
+
+    /// The number of optimized images, and the bytes they saved.
+    optimized: AtomicUsize,
+    saved: AtomicU64,
+}
+
+impl ImageOptimizer {
+    /// Locate (or download) the tools. A missing tool is reported, the images
+    /// it would optimize are copied as is.
+    pub async fn new(config: &PacklerConfig) -> Self {
+        let locate = |app: Application| async move {
+            if config.dry_run {
+                info!(target: LOG_TARGET, "DRY RUN: would optimize the images with {}", app.name());
+                return None;
+            }
+            tools::get(app, None, &config.tool_checksums)
+                .await
+                .map_err(|e| {
+                    warn!(
+                        target: LOG_TARGET,
+                        "IMG: {} is not available, its images are not optimized: {e:#}",
+                        app.name()
+                    )
+                })
+                .ok()
+        };
+
+        Self {
+            oxipng: locate(Application::Oxipng).await,
+            jpegtran: locate(Application::Jpegtran).await,
+            optimized: AtomicUsize::new(0),
+            saved: AtomicU64::new(0),
+        }
+    }
+
+    /// Optimize the image `content` of the given `format`. Returns `None` when
+    /// it cannot be optimized.
+    pub fn optimize(
+        &self,
+        format: Option<OutputFormat>,
+        content: &[u8],
+    ) -> Result<Option<Vec<u8>>, BoxedError> {
+        let (app, bin, args): (_, _, &[&str]) = match format {
+            Some(OutputFormat::Png) => (
+                Application::Oxipng,
+                &self.oxipng,
+                &["--opt", "2", "--strip", "safe", "--stdout", "-"],
+            ),
+            Some(OutputFormat::Jpeg) => (
+                Application::Jpegtran,
+                &self.jpegtran,
+                &["-copy", "none", "-optimize"],
+            ),
+            _ => return Ok(None),
+        };
+        let Some(bin) = bin else {
+            return Ok(None);
+        };
+
+        let mut child = Command::new(bin)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Written from another thread: the output may fill the pipe before
+        // the whole input is read.
+        let mut stdin = child.stdin.take().ok_or("no stdin")?;
+        let input = content.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        writer.join().map_err(|_| "cannot write the image")??;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("{} failed: {}", app.name(), stderr.trim()).into());
+        }
+
+        // Already optimal.
+        if output.stdout.is_empty() || output.stdout.len() >= content.len() {
+            return Ok(None);
+        }
+        self.optimized.fetch_add(1, Ordering::Relaxed);
+        self.saved.fetch_add(
+            (content.len() - output.stdout.len()) as u64,
+            Ordering::Relaxed,
+        );
+        Ok(Some(output.stdout))
+    }
+
+    /// Log the bytes saved by the optimization.
+    pub fn report(&self) {
+        let optimized = self.optimized.load(Ordering::Relaxed);
+        if optimized > 0 {
+            info!(
+                target: LOG_TARGET,
+                "IMG: optimized {optimized} image(s), {} bytes saved",
+                self.saved.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn only_the_smaller_images_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpegtran = dir.path().join("jpegtran");
+        std::fs::write(&jpegtran, "#!/bin/sh\ncat > /dev/null\nprintf small\n").unwrap();
+        std::fs::set_permissions(&jpegtran, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let optimizer = ImageOptimizer {
+            oxipng: None,
+            jpegtran: Some(jpegtran),
+            optimized: AtomicUsize::new(0),
+            saved: AtomicU64::new(0),
+        };
+
+        let jpeg = vec![0; 100];
+        let optimized = optimizer.optimize(Some(OutputFormat::Jpeg), &jpeg).unwrap();
+        assert_eq!(optimized.as_deref(), Some(b"small".as_slice()));
+        assert_eq!(optimizer.saved.load(Ordering::Relaxed), 95);
+
+        assert_eq!(
+            optimizer
+                .optimize(Some(OutputFormat::Jpeg), b"tiny")
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            optimizer.optimize(Some(OutputFormat::Png), &jpeg).unwrap(),
+            None
+        );
+        assert_eq!(optimizer.optimize(None, &jpeg).unwrap(), None);
+        assert_eq!(optimizer.optimized.load(Ordering::Relaxed), 1);
+    }
+}
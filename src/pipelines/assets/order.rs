@@ -0,0 +1,100 @@
+//! The order the asset pipelines run in.
+//!
+//! Each pipeline declares the pipelines whose output it needs, see
+//! [`Pipeline::dependencies`]. [`build_order`] guarantees a pipeline only
+//! starts once all its dependencies are done, so it can read their output
+//! from the [`AssetsOutput`] being built.
+//!
+//! For the hooks, all the pipelines run after the `pre_build` hook and before
+//! the metadata file is written and the `post_build` hook runs.
+//!
+//! [`AssetsOutput`]: super::AssetsOutput
+
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pipeline {
+    Images,
+    Copy,
+    Sass,
+    Tailwind,
+    CriticalCss,
+}
+
+impl Pipeline {
+    pub const ALL: [Self; 5] = [
+        Self::Images,
+        Self::Copy,
+        Self::Sass,
+        Self::Tailwind,
+        Self::CriticalCss,
+    ];
+
+    /// The pipelines that must run before this one.
+    pub fn dependencies(&self) -> &'static [Self] {
+        match self {
+            Self::Images | Self::Copy => &[],
+            // The stylesheets may reference the (hashed) images and fonts.
+            Self::Sass | Self::Tailwind => &[Self::Images, Self::Copy],
+            // Extracted from the compiled SASS entrypoint.
+            Self::CriticalCss => &[Self::Sass],
+        }
+    }
+}
+
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Images => write!(f, "images"),
+            Self::Copy => write!(f, "copy"),
+            Self::Sass => write!(f, "SASS"),
+            Self::Tailwind => write!(f, "Tailwind"),
+            Self::CriticalCss => write!(f, "critical CSS"),
+        }
+    }
+}
+
+/// All the pipelines, each one after its dependencies. Pipelines without a
+/// dependency between them keep the order of [`Pipeline::ALL`].
+pub fn build_order() -> Vec<Pipeline> {
+    fn visit(pipeline: Pipeline, visiting: &mut Vec<Pipeline>, order: &mut Vec<Pipeline>) {
+        if order.contains(&pipeline) {
+            return;
+        }
+        assert!(
+            !visiting.contains(&pipeline),
+            "Dependency cycle between the pipelines: {visiting:?}"
+        );
+
+        visiting.push(pipeline);
+        for dependency in pipeline.dependencies() {
+            visit(*dependency, visiting, order);
+        }
+        visiting.pop();
+        order.push(pipeline);
+    }
+
+    let mut order = Vec::with_capacity(Pipeline::ALL.len());
+    for pipeline in Pipeline::ALL {
+        visit(pipeline, &mut Vec::new(), &mut order);
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipelines_run_after_their_dependencies() {
+        let order = build_order();
+        assert_eq!(order.len(), Pipeline::ALL.len());
+
+        let position = |pipeline| order.iter().position(|p| *p == pipeline).unwrap();
+        for pipeline in Pipeline::ALL {
+            for dependency in pipeline.dependencies() {
+                assert!(position(*dependency) < position(pipeline));
+            }
+        }
+    }
+}
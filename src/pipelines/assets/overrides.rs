@@ -0,0 +1,318 @@
+//! Per-image processing overrides.
+//!
+//! An image can have a `<image>.packler.toml` sidecar file next to it, e.g.
+//! `hero.jpg.packler.toml`:
+//!
+//! ```toml
+//! formats = ["jpeg", "png"]
+//! widths = [640, 1280]
+//! quality = 60
+//! no_hash = false
+//! ```
+//!
+//! Images without a sidecar are only hashed and copied, and converted to the
+//! [`PacklerConfig::image_formats`].
+//!
+//! The AVIF images are encoded by the `avifenc` command of
+//! [libavif](https://github.com/AOMediaCodec/libavif), it must be installed.
+//!
+//! [`PacklerConfig::image_formats`]: crate::PacklerConfig::image_formats
+
+use image::{imageops::FilterType, ImageOutputFormat};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The images are processed in parallel: the errors must be `Send`.
+type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The suffix added to the image file name to get its sidecar.
+pub const SIDECAR_SUFFIX: &str = ".packler.toml";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageOverrides {
+    /// Output one file per format. Default: the format of the source.
+    pub formats: Vec<OutputFormat>,
+
+    /// Output one file per width, resized with its aspect ratio kept. Images
+    /// are never upscaled. Default: the size of the source, and the
+    /// [`PacklerConfig::image_widths`] of PNG and JPEG images.
+    ///
+    /// [`PacklerConfig::image_widths`]: crate::PacklerConfig::image_widths
+    pub widths: Vec<u32>,
+
+    /// The JPEG and AVIF quality, from 1 to 100.
+    pub quality: Option<u8>,
+
+    /// Keep the original file name instead of adding the content hash to it.
+    pub no_hash: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Gif,
+    /// Lossless.
+    Webp,
+    Avif,
+}
+
+impl OutputFormat {
+    /// The format of the image at `path`, from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match image::ImageFormat::from_path(path).ok()? {
+            image::ImageFormat::Png => Some(Self::Png),
+            image::ImageFormat::Jpeg => Some(Self::Jpeg),
+            image::ImageFormat::Gif => Some(Self::Gif),
+            image::ImageFormat::WebP => Some(Self::Webp),
+            image::ImageFormat::Avif => Some(Self::Avif),
+            _ => None,
+        }
+    }
+
+    /// Whether the images of this format are converted to the
+    /// [`PacklerConfig::image_formats`]. GIFs are not: they may be animated.
+    ///
+    /// [`PacklerConfig::image_formats`]: crate::PacklerConfig::image_formats
+    pub fn is_convertible(&self) -> bool {
+        matches!(self, Self::Png | Self::Jpeg)
+    }
+
+    pub fn extension(&self) -> &str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Gif => "gif",
+            Self::Webp => "webp",
+            Self::Avif => "avif",
+        }
+    }
+}
+
+/// One of the files generated from an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Variant {
+    pub width: Option<u32>,
+    pub format: OutputFormat,
+}
+
+impl Variant {
+    /// The file name of this variant, before hashing: `hero.jpg` at 640px
+    /// wide in PNG is `hero-640w.png`.
+    pub fn file_name(&self, source: &Path) -> String {
+        let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+        match self.width {
+            Some(width) => format!("{stem}-{width}w.{}", self.format.extension()),
+            None => format!("{stem}.{}", self.format.extension()),
+        }
+    }
+
+    /// This variant without upscaling: a width that is not smaller than the
+    /// `source_width` is the size of the source.
+    pub fn fit(self, source_width: Option<u32>) -> Self {
+        Self {
+            width: self
+                .width
+                .filter(|width| source_width.is_some_and(|source| *width < source)),
+            ..self
+        }
+    }
+}
+
+impl ImageOverrides {
+    /// The path of the sidecar of `image`.
+    pub fn sidecar_path(image: &Path) -> PathBuf {
+        let mut name = image.file_name().unwrap_or_default().to_owned();
+        name.push(SIDECAR_SUFFIX);
+        image.with_file_name(name)
+    }
+
+    pub fn is_sidecar(path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| name.to_string_lossy().ends_with(SIDECAR_SUFFIX))
+    }
+
+    /// Read the sidecar of `image`, if there is one.
+    pub fn load(image: &Path) -> Result<Option<Self>, BoxedError> {
+        let path = Self::sidecar_path(image);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let overrides = toml::from_str(&content)
+            .map_err(|e| format!("Invalid sidecar '{}': {e}", path.display()))?;
+        Ok(Some(overrides))
+    }
+
+    /// Whether the image must be decoded and encoded again, instead of being
+    /// copied as is.
+    pub fn needs_processing(&self) -> bool {
+        !self.formats.is_empty() || !self.widths.is_empty() || self.quality.is_some()
+    }
+
+    /// The files to generate from the image at `source`. Without
+    /// [`Self::formats`], the format of the source and the `extra_formats`.
+    /// Without [`Self::widths`], the size of the source and the
+    /// `default_widths`.
+    pub fn variants(
+        &self,
+        source: &Path,
+        extra_formats: &[OutputFormat],
+        default_widths: &[u32],
+    ) -> Result<Vec<Variant>, BoxedError> {
+        let formats = if self.formats.is_empty() {
+            let format = OutputFormat::from_path(source)
+                .filter(|format| !matches!(format, OutputFormat::Webp | OutputFormat::Avif))
+                .ok_or_else(|| {
+                    format!(
+                        "'{}' cannot be processed, only PNG, JPEG and GIF images can",
+                        source.display()
+                    )
+                })?;
+            let mut formats = vec![format];
+            formats.extend(extra_formats.iter().filter(|extra| **extra != format));
+            formats
+        } else {
+            self.formats.clone()
+        };
+
+        let widths: Vec<Option<u32>> = if self.widths.is_empty() {
+            std::iter::once(None)
+                .chain(default_widths.iter().copied().map(Some))
+                .collect()
+        } else {
+            self.widths.iter().copied().map(Some).collect()
+        };
+
+        Ok(widths
+            .iter()
+            .flat_map(|&width| formats.iter().map(move |&format| Variant { width, format }))
+            .collect())
+    }
+
+    /// Encode the image `content` as `variant`.
+    pub fn render(&self, content: &[u8], variant: Variant) -> Result<Vec<u8>, BoxedError> {
+        let mut image = image::load_from_memory(content)?;
+
+        if let Some(width) = variant.width.filter(|&width| width < image.width()) {
+            image = image.resize(width, u32::MAX, FilterType::Lanczos3);
+        }
+
+        let format = match variant.format {
+            OutputFormat::Png => ImageOutputFormat::Png,
+            OutputFormat::Jpeg => {
+                // JPEG has no alpha channel.
+                image = image.to_rgb8().into();
+                ImageOutputFormat::Jpeg(self.quality.unwrap_or(75).clamp(1, 100))
+            }
+            OutputFormat::Gif => ImageOutputFormat::Gif,
+            OutputFormat::Webp => ImageOutputFormat::WebP,
+            OutputFormat::Avif => ImageOutputFormat::Png,
+        };
+
+        let mut output = Cursor::new(Vec::new());
+        image.write_to(&mut output, format)?;
+        match variant.format {
+            OutputFormat::Avif => encode_avif(&output.into_inner(), self.quality.unwrap_or(75)),
+            _ => Ok(output.into_inner()),
+        }
+    }
+}
+
+/// Encode the `png` image to AVIF with `avifenc`.
+fn encode_avif(png: &[u8], quality: u8) -> Result<Vec<u8>, BoxedError> {
+    let avifenc = which::which("avifenc")
+        .map_err(|e| format!("avifenc is needed to generate AVIF images: {e}"))?;
+
+    // The images are encoded in parallel: each one needs its own files.
+    let name = format!(
+        "packler-{}-{:016x}",
+        std::process::id(),
+        RandomState::new().hash_one(png)
+    );
+    let input = std::env::temp_dir().join(format!("{name}.png"));
+    let output = input.with_extension("avif");
+    std::fs::write(&input, png)?;
+
+    let result = Command::new(avifenc)
+        .arg("-q")
+        .arg(quality.clamp(1, 100).to_string())
+        .arg(&input)
+        .arg(&output)
+        .output();
+    let _ = std::fs::remove_file(&input);
+    let result = result?;
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        return Err(format!("avifenc failed: {}", stderr.trim()).into());
+    }
+
+    let avif = std::fs::read(&output);
+    let _ = std::fs::remove_file(&output);
+    Ok(avif?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecar_variants_and_render() {
+        let overrides: ImageOverrides =
+            toml::from_str("formats = [\"jpeg\", \"png\"]\nwidths = [8, 64]\nquality = 60")
+                .unwrap();
+        assert!(overrides.needs_processing());
+        assert!(!overrides.no_hash);
+
+        let source = Path::new("images/hero.png");
+        let variants = overrides.variants(source, &[], &[320]).unwrap();
+        let names: Vec<String> = variants.iter().map(|v| v.file_name(source)).collect();
+        assert_eq!(
+            names,
+            ["hero-8w.jpg", "hero-8w.png", "hero-64w.jpg", "hero-64w.png"]
+        );
+
+        let mut png = Cursor::new(Vec::new());
+        image::RgbImage::new(32, 16)
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        // Resized to 8px wide, but never upscaled.
+        let small = image::load_from_memory(&overrides.render(&png, variants[1]).unwrap()).unwrap();
+        assert_eq!((small.width(), small.height()), (8, 4));
+        let large = image::load_from_memory(&overrides.render(&png, variants[2]).unwrap()).unwrap();
+        assert_eq!((large.width(), large.height()), (32, 16));
+        assert_eq!(variants[2].fit(Some(32)).width, None);
+        assert_eq!(variants[1].fit(Some(32)).width, Some(8));
+
+        let names: Vec<String> = ImageOverrides::default()
+            .variants(source, &[OutputFormat::Webp], &[320])
+            .unwrap()
+            .iter()
+            .map(|v| v.file_name(source))
+            .collect();
+        assert_eq!(
+            names,
+            ["hero.png", "hero.webp", "hero-320w.png", "hero-320w.webp"]
+        );
+
+        assert!(toml::from_str::<ImageOverrides>("formats = [\"bmp\"]").is_err());
+        assert_eq!(
+            ImageOverrides::sidecar_path(source),
+            Path::new("images/hero.png.packler.toml")
+        );
+        assert!(ImageOverrides::is_sidecar(Path::new(
+            "hero.png.packler.toml"
+        )));
+    }
+}
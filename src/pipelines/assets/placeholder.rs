@@ -0,0 +1,198 @@
+//! Tiny placeholders a frontend can render while an image loads.
+//!
+//! Computing them means decoding the whole image, so they are cached (in the
+//! intermediate directory) by the hash of the image content.
+
+use super::images::LOG_TARGET;
+use crate::{common, PacklerConfig};
+use base64::Engine;
+use image::imageops::FilterType;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    path::Path,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// Images smaller than this (in bytes) do not get a placeholder: they would
+/// load about as fast as it.
+pub const MIN_PLACEHOLDER_SOURCE_SIZE: usize = 2 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlaceholderKind {
+    /// A [blurhash](https://blurha.sh/) string.
+    Blurhash,
+    /// A `data:image/png;base64,...` URL of a (at most) 16x16 thumbnail.
+    TinyDataUrl,
+}
+
+impl PlaceholderKind {
+    fn name(&self) -> &str {
+        match self {
+            Self::Blurhash => "blurhash",
+            Self::TinyDataUrl => "tiny-dataurl",
+        }
+    }
+
+    /// Compute the placeholder of an image. Returns `None` for vector and
+    /// very small images, or if the image cannot be decoded.
+    pub fn compute(&self, path: &Path, content: &[u8]) -> Option<String> {
+        let is_svg = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+        if is_svg || content.len() < MIN_PLACEHOLDER_SOURCE_SIZE {
+            return None;
+        }
+
+        let image = match image::load_from_memory(content) {
+            Ok(image) => image,
+            Err(e) => {
+                warn!(
+                    target: LOG_TARGET,
+                    "IMG: cannot decode '{}' for its placeholder: {e}",
+                    path.display()
+                );
+                return None;
+            }
+        };
+
+        match self {
+            Self::Blurhash => {
+                let thumbnail = image.resize(32, 32, FilterType::Triangle).to_rgba8();
+                blurhash::encode(4, 3, thumbnail.width(), thumbnail.height(), &thumbnail)
+                    .map_err(|e| {
+                        warn!(
+                            target: LOG_TARGET,
+                            "IMG: cannot compute blurhash of '{}': {e}",
+                            path.display()
+                        )
+                    })
+                    .ok()
+            }
+            Self::TinyDataUrl => {
+                let thumbnail = image.resize(16, 16, FilterType::Triangle);
+                let mut png = Cursor::new(Vec::new());
+                thumbnail
+                    .write_to(&mut png, image::ImageOutputFormat::Png)
+                    .map_err(|e| {
+                        warn!(
+                            target: LOG_TARGET,
+                            "IMG: cannot encode placeholder of '{}': {e}",
+                            path.display()
+                        )
+                    })
+                    .ok()?;
+                let encoded = base64::engine::general_purpose::STANDARD.encode(png.into_inner());
+                Some(format!("data:image/png;base64,{encoded}"))
+            }
+        }
+    }
+}
+
+/// Placeholders already computed, by kind and content hash.
+///
+/// Shared between the threads processing the images.
+pub struct PlaceholderCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, String>>,
+}
+
+impl PlaceholderCache {
+    pub fn load(cfg: &PacklerConfig) -> Self {
+        let path = cfg.intermediate_dir().join("placeholders.json");
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// The placeholder of the image at `path`, from the cache if possible.
+    pub fn get_or_compute(
+        &self,
+        kind: PlaceholderKind,
+        path: &Path,
+        content: &[u8],
+        hash: u64,
+    ) -> Option<String> {
+        let key = format!("{}-{hash:016x}", kind.name());
+        if let Some(placeholder) = self.entries().get(&key) {
+            debug!(target: LOG_TARGET, "IMG: placeholder of '{}' found in cache", path.display());
+            return Some(placeholder.clone());
+        }
+
+        // Not locked while computing, so the other images are not blocked.
+        let placeholder = kind.compute(path, content)?;
+        self.entries().insert(key, placeholder.clone());
+        Some(placeholder)
+    }
+
+    pub fn save(&self) {
+        let res = std::fs::create_dir_all(self.intermediate_dir()).and_then(|_| {
+            std::fs::write(
+                &self.path,
+                serde_json::to_string(&*self.entries()).unwrap_or_default(),
+            )
+        });
+
+        match res {
+            Ok(()) => common::record_write(&self.path),
+            Err(e) => warn!(target: LOG_TARGET, "IMG: could not save the placeholders cache: {e}"),
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, String>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn intermediate_dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new("."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noisy_png() -> Vec<u8> {
+        let image = image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x * y) % 256) as u8])
+        });
+        let mut png = Cursor::new(Vec::new());
+        image
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        png.into_inner()
+    }
+
+    #[test]
+    fn placeholders_of_raster_images() {
+        let png = noisy_png();
+        assert!(png.len() >= MIN_PLACEHOLDER_SOURCE_SIZE);
+
+        let blurhash = PlaceholderKind::Blurhash.compute(Path::new("a.png"), &png);
+        assert!(blurhash.is_some_and(|hash| !hash.is_empty()));
+
+        let data_url = PlaceholderKind::TinyDataUrl.compute(Path::new("a.png"), &png);
+        assert!(data_url.is_some_and(|url| url.starts_with("data:image/png;base64,")));
+    }
+
+    #[test]
+    fn no_placeholder_for_svg_and_small_images() {
+        let png = noisy_png();
+        assert_eq!(
+            PlaceholderKind::Blurhash.compute(Path::new("a.svg"), &png),
+            None
+        );
+        assert_eq!(
+            PlaceholderKind::Blurhash.compute(Path::new("a.png"), &png[..100]),
+            None
+        );
+    }
+}
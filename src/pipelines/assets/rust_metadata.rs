@@ -0,0 +1,90 @@
+//! The metadata as Rust source, see [`PacklerConfig::metadata_rust_output`].
+//!
+//! The generated file is meant to be `include!`d by the backend, e.g. from a
+//! `build.rs` writing it into `OUT_DIR`:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+//!
+//! let logo = asset_path("images/logo.png").unwrap();
+//! ```
+//!
+//! The assets are then resolved at compile time, without reading the JSON
+//! metadata file.
+
+use super::{AssetsOutput, Error};
+use crate::{common, PacklerConfig};
+use std::fmt::Write;
+
+/// Render the logical → processed path mapping of `metadata`.
+pub fn render(metadata: &AssetsOutput) -> String {
+    let assets: Vec<(String, String)> = metadata
+        .by_logical_path()
+        .into_iter()
+        .map(|(logical, processed)| (common::to_slash(logical), common::to_slash(processed)))
+        .collect();
+
+    let mut source = String::from("// @generated by Packler, do not edit.\n\n");
+
+    source.push_str("/// The logical and processed paths of all the assets.\n");
+    source.push_str("pub const ASSETS: &[(&str, &str)] = &[\n");
+    for (logical, processed) in &assets {
+        // The `Debug` output of a `str` is a valid Rust string literal.
+        writeln!(source, "    ({logical:?}, {processed:?}),").unwrap();
+    }
+    source.push_str("];\n\n");
+
+    source.push_str("/// The processed path of an asset, from its logical path.\n");
+    source.push_str("pub fn asset_path(logical_path: &str) -> Option<&'static str> {\n");
+    source.push_str("    match logical_path {\n");
+    for (logical, processed) in &assets {
+        writeln!(source, "        {logical:?} => Some({processed:?}),").unwrap();
+    }
+    source.push_str("        _ => None,\n    }\n}\n");
+
+    source
+}
+
+pub fn write(config: &PacklerConfig, metadata: &AssetsOutput) -> Result<(), Error> {
+    let Some(path) = &config.metadata_rust_output else {
+        return Ok(());
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(Error::CannotWriteMetadataFile)?;
+    }
+
+    std::fs::write(path, render(metadata)).map_err(Error::CannotWriteMetadataFile)?;
+    common::record_write(path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipelines::assets::AssetMetadata;
+    use std::path::PathBuf;
+
+    #[test]
+    fn render_the_mapping() {
+        let asset = |logical: &str, processed: &str| {
+            AssetMetadata::new(
+                PathBuf::from("assets").join(logical),
+                logical,
+                processed,
+                0,
+                String::new(),
+            )
+        };
+        let metadata = AssetsOutput {
+            images: vec![asset("images/a \"b\".png", "images/a \"b\"-01.png")],
+            sass: vec![asset("css/app.scss", "css/app-02.css")],
+            ..Default::default()
+        };
+
+        let source = render(&metadata);
+        assert!(source.contains(r#"("css/app.scss", "css/app-02.css"),"#));
+        assert!(source.contains(r#""images/a \"b\".png" => Some("images/a \"b\"-01.png"),"#));
+        assert!(source.find("css/app.scss") < source.find("images/a"));
+    }
+}
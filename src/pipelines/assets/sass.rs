@@ -9,27 +9,169 @@
 //!
 
 use crate::common::{self};
-use crate::pipelines::assets::{AssetMetadata, Error};
+use crate::pipelines::assets::{incremental::Incremental, AssetMetadata, Error, HashExclude};
+use crate::progress::{self, Phase};
 use crate::tools::{self, Application};
-use crate::PacklerConfig;
+use crate::{PacklerConfig, Profile};
 use futures_util::future::join_all;
-use log::{error, info, warn};
+use globset::Glob;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::iter::Iterator;
 use std::path::{Path, PathBuf};
 
-pub fn clean_dist_dir(cfg: &PacklerConfig) {
+/// The `log` target of this pipeline, see [`crate::pipelines`].
+pub const LOG_TARGET: &str = "packler::sass";
+
+pub fn clean_dist_dir(cfg: &PacklerConfig) -> Result<(), Error> {
     let sass_dir = cfg.dist_sass_dir();
 
-    if sass_dir.exists() {
-        std::fs::remove_dir_all(&sass_dir)
-            .unwrap_or_else(|_| panic!("Could not remove {}", sass_dir.display()))
+    if sass_dir.exists() && cfg.dry_run {
+        info!(target: LOG_TARGET, "DRY RUN: would remove '{}'", sass_dir.display());
+    } else if sass_dir.exists() {
+        std::fs::remove_dir_all(&sass_dir).map_err(Error::CannotRemoveDistDir)?;
+    }
+    Ok(())
+}
+
+/// Expand the glob entrypoints (e.g., `pages/*.scss`) against the SASS
+/// directory, skipping the partials. The other entrypoints are kept as is.
+///
+/// The result is sorted and deduplicated so the metadata file does not depend
+/// on the order the files are found in.
+pub fn expand_entrypoints<P, E>(
+    config: &PacklerConfig,
+    entrypoints: E,
+) -> Result<Vec<PathBuf>, Error>
+where
+    P: AsRef<Path>,
+    E: IntoIterator<Item = P>,
+{
+    let source_dir = config.source_sass_dir();
+    let mut expanded = BTreeSet::new();
+
+    for entry in entrypoints {
+        let entry = entry.as_ref();
+        let pattern = entry.to_string_lossy();
+        if !pattern.contains(['*', '?', '[', '{']) {
+            expanded.insert(entry.to_owned());
+            continue;
+        }
+
+        let glob = Glob::new(&pattern)
+            .map_err(Error::InvalidEntryPointGlob)?
+            .compile_matcher();
+        let matches: Vec<PathBuf> = common::walk_dir(&source_dir, config.include_hidden)
+            .filter_map(Result::ok)
+            .filter(|file| file.file_type().is_file())
+            .filter(|file| !file.file_name().to_string_lossy().starts_with('_'))
+            .filter_map(|file| {
+                let relative = file.path().strip_prefix(&source_dir).ok()?;
+                glob.is_match(relative).then(|| relative.to_owned())
+            })
+            .collect();
+
+        debug!(target: LOG_TARGET, "SASS: '{pattern}' matches {matches:?}");
+        if matches.is_empty() {
+            if !config.allow_empty_globs {
+                return Err(Error::EntryPointGlobMatchesNothing(pattern.into_owned()));
+            }
+            warn!(target: LOG_TARGET, "SASS: '{pattern}' does not match any file");
+        }
+        expanded.extend(matches);
+    }
+
+    Ok(expanded.into_iter().collect())
+}
+
+/// The files `source` loads with `@import`, `@use` or `@forward`, resolved
+/// like sass does: relative to `source`, then to the SASS directory and to the
+/// [`PacklerConfig::sass_load_paths`]. The built-in modules (e.g.,
+/// `sass:math`) and the plain CSS imports are skipped.
+pub fn direct_imports(config: &PacklerConfig, source: &Path, content: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![
+        source.parent().unwrap_or(Path::new("")).to_owned(),
+        config.source_sass_dir(),
+    ];
+    dirs.extend(config.sass_load_paths.iter().cloned());
+    content
+        .lines()
+        .map(str::trim_start)
+        .filter(|line| {
+            ["@import", "@use", "@forward"]
+                .iter()
+                .any(|rule| line.starts_with(rule))
+        })
+        // The quoted URLs, `@import "a", "b";` imports both.
+        .flat_map(|line| line.split(['"', '\'']).skip(1).step_by(2))
+        .filter(|url| !url.starts_with("sass:") && !url.contains("://") && !url.ends_with(".css"))
+        .filter_map(|url| resolve_import(&dirs, url))
+        .collect()
+}
+
+/// The files `source` depends on: the files it imports, the files they import
+/// and so on. Sorted, without `source` itself.
+pub fn dependencies(config: &PacklerConfig, source: &Path) -> Vec<PathBuf> {
+    let mut found = BTreeSet::new();
+    let mut to_visit = vec![source.to_owned()];
+    while let Some(file) = to_visit.pop() {
+        let Ok(content) = std::fs::read_to_string(&file) else {
+            continue;
+        };
+        for import in direct_imports(config, &file, &content) {
+            if import != source && found.insert(import.clone()) {
+                to_visit.push(import);
+            }
+        }
+    }
+    found.into_iter().collect()
+}
+
+/// The entrypoints of `sass` (the metadata of a previous build) that must be
+/// compiled again after `changed`: they are one of them, or depend on one.
+pub fn affected_entrypoints<'a>(sass: &'a [AssetMetadata], changed: &[PathBuf]) -> Vec<&'a Path> {
+    let changed: Vec<PathBuf> = changed
+        .iter()
+        .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone()))
+        .collect();
+    let is_changed = |path: &Path| {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+        changed.contains(&path)
+    };
+
+    sass.iter()
+        .filter(|asset| !asset.mutable)
+        .filter(|asset| {
+            is_changed(&asset.source_path) || asset.dependencies.iter().any(|dep| is_changed(dep))
+        })
+        .map(|asset| asset.source_path.as_path())
+        .collect()
+}
+
+/// The file `url` loads: `a/b` is `a/b.scss`, `a/_b.scss`, or
+/// `a/b/_index.scss` (or their `.sass` equivalent).
+fn resolve_import(dirs: &[PathBuf], url: &str) -> Option<PathBuf> {
+    let url = Path::new(url);
+    let name = url.file_name()?.to_str()?;
+    let mut candidates = vec![url.to_owned(), url.with_file_name(format!("_{name}"))];
+    for extension in ["scss", "sass"] {
+        candidates.push(url.with_file_name(format!("{name}.{extension}")));
+        candidates.push(url.with_file_name(format!("_{name}.{extension}")));
+        candidates.push(url.join(format!("_index.{extension}")));
+        candidates.push(url.join(format!("index.{extension}")));
     }
+
+    dirs.iter()
+        .flat_map(|dir| candidates.iter().map(move |candidate| dir.join(candidate)))
+        .find(|path| path.is_file())
 }
 
 pub async fn process<E, P>(
     config: &PacklerConfig,
     entry_points: E,
+    incremental: Option<&Incremental>,
+    profile: Profile,
 ) -> Result<Vec<AssetMetadata>, Box<dyn std::error::Error>>
 where
     P: AsRef<Path> + Send + Clone,
@@ -38,7 +180,7 @@ where
     let sass_cfg = SassRun {
         config: config.clone(),
     };
-    sass_cfg.start(entry_points).await
+    sass_cfg.start(entry_points, incremental, profile).await
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,55 +202,162 @@ pub struct SassRun {
 
 impl SassRun {
     pub fn intermediate_dir(&self) -> PathBuf {
-        // FIXME: hardcoded path
-        self.config.target.join("packler").join("sass")
+        self.config.intermediate_dir().join("sass")
     }
 
     pub fn clean_intermediate_folder(&self) {
         let dir = self.intermediate_dir();
 
-        if dir.exists() {
+        if dir.exists() && self.config.dry_run {
+            info!(target: LOG_TARGET, "DRY RUN: would remove '{}'", dir.display());
+        } else if dir.exists() {
             match std::fs::remove_dir_all(&dir) {
-                Ok(()) => info!("SASS: Intermediate folder cleared"),
-                Err(e) => warn!("SASS: Could not remove intermediate folder: {e}"),
+                Ok(()) => info!(target: LOG_TARGET, "SASS: Intermediate folder cleared"),
+                Err(e) => {
+                    warn!(target: LOG_TARGET, "SASS: Could not remove intermediate folder: {e}")
+                }
             }
         }
     }
 
     /// Spawn the pipeline for this asset type.
+    ///
+    /// With `incremental`, only the entrypoints whose hash changed are compiled
+    /// again: the hash covers the entrypoint and all the files it imports, see
+    /// [`dependencies`].
+    ///
+    /// The CSS is compressed with [`Profile::Release`].
     pub async fn start<P: AsRef<Path> + Send + Clone, E: IntoIterator<Item = P>>(
         self,
         entrypoints: E,
+        incremental: Option<&Incremental>,
+        profile: Profile,
     ) -> Result<Vec<AssetMetadata>, Box<dyn std::error::Error>> {
-        info!("SASS: Start SASS Pipeline");
+        info!(target: LOG_TARGET, "SASS: Start SASS Pipeline");
 
-        let sass = tools::get(Application::Sass, Some(&self.config.sass_version)).await?;
+        let entrypoints = expand_entrypoints(&self.config, entrypoints)?;
 
-        self.clean_intermediate_folder();
-        clean_dist_dir(&self.config);
+        // An image-only project must build without sass (nor network access).
+        if entrypoints.is_empty() {
+            info!(target: LOG_TARGET, "SASS: No entrypoint. Skip");
+            return Ok(Vec::new());
+        }
 
-        let futures = entrypoints
-            .into_iter()
-            .map(|entry| self.run(&sass, entry, false));
+        // Compiling writes in the intermediate directory, and may download
+        // sass: the outputs (and their hash) are unknown in a dry run.
+        if self.config.dry_run {
+            for entry in &entrypoints {
+                info!(
+                    target: LOG_TARGET,
+                    "DRY RUN: would compile '{}' into '{}'",
+                    entry.display(),
+                    self.config.dist_sass_dir().display()
+                );
+            }
+            return Ok(Vec::new());
+        }
+
+        let sass = match &self.config.sass_binary {
+            Some(sass) if common::is_executable(sass).await? => sass.clone(),
+            Some(sass) => return Err(Box::new(Error::SassBinaryNotExecutable(sass.clone()))),
+            None => {
+                tools::get_for_platform(
+                    Application::Sass,
+                    Some(&self.config.sass_version),
+                    self.config.sass_platform_override.as_deref(),
+                    &self.config.tool_checksums,
+                )
+                .await?
+            }
+        };
+        let mut files = Vec::new();
+
+        let to_compile = match incremental {
+            Some(incremental) => {
+                let mut to_compile = Vec::new();
+                for entry in entrypoints {
+                    let source = self.config.source_sass_dir().join(&entry);
+                    let dependencies = dependencies(&self.config, &source);
+                    let previous = self
+                        .source_hash(&source, &dependencies, profile == Profile::Release)
+                        .and_then(|hash| incremental.unchanged(&self.config, &source, hash));
+                    match previous {
+                        Some(previous) => {
+                            info!(
+                                target: LOG_TARGET,
+                                "SASS: '{}' did not change. Skip",
+                                entry.display()
+                            );
+                            files.push(previous.clone());
+                        }
+                        _ => to_compile.push(entry),
+                    }
+                }
+                to_compile
+            }
+            None => {
+                self.clean_intermediate_folder();
+                clean_dist_dir(&self.config)?;
+                entrypoints
+            }
+        };
+
+        progress::start(Phase::Sass, to_compile.len());
+        let (run, sass) = (&self, &sass);
+        let futures = to_compile.iter().map(|entry| async move {
+            let output = run.run(sass, entry, profile == Profile::Release).await;
+            progress::advance(Phase::Sass);
+            output
+        });
         let results = join_all(futures).await;
 
         // Only copy to final dist if all files are OK.
 
-        let files = results
-            .iter()
-            .filter_map(|r| match r {
-                Ok(output) => Some(output.clone()),
-                Err(_e) => None,
-            })
-            .collect();
+        let mut failures = Vec::new();
+        for (entry, result) in to_compile.into_iter().zip(results) {
+            match result {
+                Ok(output) => files.push(output),
+                Err(e) => {
+                    error!(target: LOG_TARGET, "SASS: '{}' failed: {e}", entry.display());
+                    failures.push((entry, e));
+                }
+            }
+        }
 
-        // FIXME: Return the errors as well!
+        if !failures.is_empty() {
+            return Err(Box::new(Error::SassCompilationFailed(failures)));
+        }
         Ok(files)
     }
 
+    /// Whether the source maps are written, see
+    /// [`PacklerConfig::sass_source_maps`].
+    fn source_maps(&self, compress: bool) -> bool {
+        self.config.sass_source_maps.unwrap_or(!compress)
+    }
+
+    /// The hash of `source` and of its `dependencies`, with the settings the
+    /// CSS depends on.
+    fn source_hash(&self, source: &Path, dependencies: &[PathBuf], compress: bool) -> Option<u64> {
+        let source_maps = self.source_maps(compress);
+        let mut hashed = format!(
+            "{}\n{compress}\n{source_maps}\n{:?}\n",
+            self.config.sass_version, self.config.sass_extra_args
+        )
+        .into_bytes();
+        hashed.extend(std::fs::read(source).ok()?);
+        // A partial resolved elsewhere (e.g., a new `_colors.scss` next to the
+        // entrypoint) changes the CSS too.
+        for dependency in dependencies {
+            hashed.extend(dependency.to_string_lossy().as_bytes());
+            hashed.extend(std::fs::read(dependency).ok()?);
+        }
+        Some(seahash::hash(&hashed))
+    }
+
     pub async fn run<P: AsRef<Path> + Send>(
         &self,
-        sass_path: &PathBuf,
+        sass_path: &Path,
         entrypoint: P,
         compress: bool,
     ) -> Result<AssetMetadata, Box<dyn std::error::Error>> {
@@ -118,6 +367,7 @@ impl SassRun {
 
         if !original_path.exists() {
             error!(
+                target: LOG_TARGET,
                 "Entrypoint '{}' does not exist.",
                 &entrypoint.as_ref().display()
             );
@@ -126,66 +376,316 @@ impl SassRun {
             )));
         }
 
+        let dependencies = dependencies(&self.config, &original_path);
+        let source_hash = self
+            .source_hash(&original_path, &dependencies, compress)
+            .unwrap_or(0);
         let path_str = original_path.display().to_string();
-        let entrypoint_filestem = original_path.file_stem().unwrap().to_string_lossy();
+        let entrypoint_filestem = original_path
+            .file_stem()
+            .ok_or("the entrypoint has no file name")?
+            .to_string_lossy();
 
         let mut prehash_file_path = self.intermediate_dir();
         prehash_file_path.push(&entrypoint);
         prehash_file_path.set_extension("css");
 
-        let args = &[
-            "--no-source-map",
+        // Self-contained: the map is served without the sources.
+        let source_maps = self.source_maps(compress);
+        let load_paths: Vec<String> = self
+            .config
+            .sass_load_paths
+            .iter()
+            .map(|dir| format!("--load-path={}", dir.display()))
+            .collect();
+        let prehash_file_str = prehash_file_path.display().to_string();
+        let mut args = vec![
+            if source_maps {
+                "--embed-sources"
+            } else {
+                "--no-source-map"
+            },
             "-s",
             style,
-            &path_str,
-            &prehash_file_path.display().to_string(),
         ];
+        args.extend(load_paths.iter().map(String::as_str));
+        // Checked by `PacklerConfig::validate`: the input and the output stay
+        // the last two.
+        args.extend(self.config.sass_extra_args.iter().map(String::as_str));
+        args.extend([path_str.as_str(), prehash_file_str.as_str()]);
 
         // SASS Compile
-        log::info!("SASS: compiling sass/scss (into {prehash_file_path:?})");
-        common::run_command(Application::Sass.name(), sass_path, args).await?;
-
-        // Hash Content
-        log::info!("SASS: hashing file content");
-        let css = tokio::fs::read_to_string(&prehash_file_path).await?;
+        log::info!(target: LOG_TARGET, "SASS: compiling sass/scss (into {prehash_file_path:?})");
+        common::run_command_captured(Application::Sass.name(), sass_path, &args).await?;
+        common::record_write(&prehash_file_path);
+
+        // Hash Content, as written by sass: compressed or not, the hash changes
+        // with the style.
+        log::info!(target: LOG_TARGET, "SASS: hashing file content");
+        let mut css = tokio::fs::read_to_string(&prehash_file_path).await?;
         let hash = seahash::hash(css.as_bytes());
 
-        // Copy to intermediate dir
-        let final_file_name = format!("{entrypoint_filestem}-{hash:x}.css");
+        let logical_path = original_path.strip_prefix(&self.config.assets_source_dir)?;
+        let final_file_name = if HashExclude::new(&self.config)?.is_excluded(logical_path) {
+            format!("{entrypoint_filestem}.css")
+        } else {
+            format!("{entrypoint_filestem}-{hash:016x}.css")
+        };
         let mut final_file_path = self.config.dist_sass_dir();
         final_file_path.push(&entrypoint);
         final_file_path.set_file_name(&final_file_name);
 
-        log::info!("SASS: moving file to final destination '{final_file_path:?}");
+        // Named after its CSS, which must point to the new name.
+        let source_map = if source_maps {
+            let map_file_name = format!("{final_file_name}.map");
+            css = point_to_source_map(
+                &css,
+                &format!("{entrypoint_filestem}.css.map"),
+                &map_file_name,
+            );
+            tokio::fs::write(&prehash_file_path, &css).await?;
+            Some((
+                prehash_file_path.with_extension("css.map"),
+                final_file_path.with_file_name(map_file_name),
+            ))
+        } else {
+            None
+        };
+        let integrity = self.config.integrity_algorithm.integrity(css.as_bytes());
 
-        if let Some(dir) = final_file_path.parent() {
-            std::fs::create_dir_all(dir).expect("Could not create final directory")
+        if self.config.dry_run {
+            log::info!(
+                target: LOG_TARGET,
+                "DRY RUN: would write '{}'",
+                final_file_path.display()
+            );
+        } else {
+            log::info!(
+                target: LOG_TARGET,
+                "SASS: moving file to final destination '{final_file_path:?}"
+            );
+            move_to_dist(&prehash_file_path, &final_file_path)?;
+            if let Some((prehash_map_path, final_map_path)) = &source_map {
+                move_to_dist(prehash_map_path, final_map_path)?;
+            }
         }
 
-        // Using fs::rename with SELinux would _not_ set the right label on the
-        // new file. It would stay `unlabeled_t`. This is annoying if we want to
-        // serve those files from a container for example (it would need the
-        // `container_file_t` label.)
-        // Doing the copy+remove circumvents the issue ¯\_(ツ)_/¯
-        std::fs::copy(&prehash_file_path, &final_file_path)
-            .expect("error copying the compiled SASS file");
-
-        std::fs::remove_file(&prehash_file_path)
-            .expect("error deleting the intermediate SASS file");
-
         let metadata = AssetMetadata {
-            source_path: original_path.clone(),
-            logical_path: original_path
-                .strip_prefix(&self.config.assets_source_dir)
-                .unwrap()
-                .into(),
-            processed_relative_path: final_file_path
-                .strip_prefix(&self.config.dist_dir)
-                .unwrap()
-                .into(),
-            hash,
+            source_hash,
+            dependencies,
+            source_map: source_map
+                .map(|(_, path)| path.strip_prefix(&self.config.dist_dir).map(Path::to_owned))
+                .transpose()?,
+            ..AssetMetadata::new(
+                &original_path,
+                logical_path,
+                final_file_path.strip_prefix(&self.config.dist_dir)?,
+                hash,
+                integrity,
+            )
         };
 
         Ok(metadata)
     }
 }
+
+/// Move a compiled file from the intermediate directory to dist, creating its
+/// directory. Also used by the other CSS pipelines, e.g. [`super::tailwind`].
+pub(crate) fn move_to_dist(
+    prehash_file_path: &Path,
+    final_file_path: &Path,
+) -> std::io::Result<()> {
+    if let Some(dir) = final_file_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    // Using fs::rename with SELinux would _not_ set the right label on the
+    // new file. It would stay `unlabeled_t`. This is annoying if we want to
+    // serve those files from a container for example (it would need the
+    // `container_file_t` label.)
+    // Doing the copy+remove circumvents the issue ¯\_(ツ)_/¯
+    std::fs::copy(prehash_file_path, final_file_path)?;
+    common::record_write(final_file_path);
+
+    std::fs::remove_file(prehash_file_path)
+}
+
+/// Point the `sourceMappingURL` comment of `css` to `map_file_name`, instead
+/// of the map written by sass (`previous`).
+fn point_to_source_map(css: &str, previous: &str, map_file_name: &str) -> String {
+    css.replace(
+        &format!("/*# sourceMappingURL={previous} */"),
+        &format!("/*# sourceMappingURL={map_file_name} */"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_entrypoint_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().to_owned(),
+            ..Default::default()
+        };
+        let pages = config.source_sass_dir().join("pages");
+        std::fs::create_dir_all(&pages).unwrap();
+        for name in ["b.scss", "a.scss", "_partial.scss"] {
+            std::fs::write(pages.join(name), "").unwrap();
+        }
+
+        let expanded =
+            expand_entrypoints(&config, ["pages/*.scss", "main.scss", "pages/a.scss"]).unwrap();
+        assert_eq!(
+            expanded,
+            [
+                PathBuf::from("main.scss"),
+                PathBuf::from("pages/a.scss"),
+                PathBuf::from("pages/b.scss"),
+            ]
+        );
+
+        assert!(matches!(
+            expand_entrypoints(&config, ["page/*.scss"]),
+            Err(Error::EntryPointGlobMatchesNothing(_))
+        ));
+        let config = PacklerConfig {
+            allow_empty_globs: true,
+            ..config
+        };
+        assert!(expand_entrypoints(&config, ["page/*.scss"])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn resolve_the_direct_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().to_owned(),
+            ..Default::default()
+        };
+        let sass_dir = config.source_sass_dir();
+        std::fs::create_dir_all(sass_dir.join("pages")).unwrap();
+        std::fs::create_dir_all(sass_dir.join("theme")).unwrap();
+        for name in ["_colors.scss", "theme/_index.scss", "pages/_local.sass"] {
+            std::fs::write(sass_dir.join(name), "").unwrap();
+        }
+
+        let source = sass_dir.join("pages").join("home.scss");
+        let content = r#"
+            @use "sass:math";
+            @use 'colors' as c;
+            @import "theme", "local", "missing";
+            @import "https://fonts.example.com/font.css";
+            .home { color: c.$primary; }
+        "#;
+        assert_eq!(
+            direct_imports(&config, &source, content),
+            [
+                sass_dir.join("_colors.scss"),
+                sass_dir.join("theme/_index.scss"),
+                sass_dir.join("pages/_local.sass"),
+            ]
+        );
+
+        // Through `theme/_index.scss`.
+        std::fs::write(sass_dir.join("theme/_index.scss"), "@use 'colors';").unwrap();
+        std::fs::write(&source, "@import 'theme';").unwrap();
+        let dependencies = dependencies(&config, &source);
+        assert_eq!(
+            dependencies,
+            [
+                sass_dir.join("_colors.scss"),
+                sass_dir.join("theme/_index.scss")
+            ]
+        );
+
+        let home = AssetMetadata {
+            dependencies,
+            ..AssetMetadata::new(
+                &source,
+                "sass/pages/home.scss",
+                "css/pages/home-0000000000000000.css",
+                0,
+                String::new(),
+            )
+        };
+        let sass = [home];
+        let affected = |changed: &str| affected_entrypoints(&sass, &[sass_dir.join(changed)]);
+        assert_eq!(affected("_colors.scss"), [source.as_path()]);
+        assert!(affected("pages/_local.sass").is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_sass_binary_must_be_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().to_owned(),
+            sass_binary: Some(dir.path().join("sass")),
+            ..Default::default()
+        };
+
+        let error = process(&config, ["main.scss"], None, Profile::Debug)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::SassBinaryNotExecutable(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn source_maps_are_named_after_their_css() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        // Writes the output like dart-sass, the last argument.
+        let sass = dir.path().join("sass");
+        std::fs::write(
+            &sass,
+            "#!/bin/sh\nfor out; do :; done\nmkdir -p \"$(dirname \"$out\")\"\n\
+             if [ \"$1\" = --embed-sources ]; then\n\
+             printf 'a{}\\n\\n/*# sourceMappingURL=main.css.map */\\n' > \"$out\"\n\
+             printf '{}' > \"$out.map\"\nelse printf 'a{}\\n' > \"$out\"; fi\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&sass, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            target: dir.path().join("target"),
+            sass_binary: Some(sass),
+            // Before the input and the output.
+            sass_load_paths: vec![dir.path().join("shared")],
+            sass_extra_args: vec!["--quiet-deps".to_owned()],
+            ..Default::default()
+        };
+        std::fs::create_dir_all(config.source_sass_dir()).unwrap();
+        std::fs::write(config.source_sass_dir().join("main.scss"), "a {}").unwrap();
+
+        let debug = process(&config, ["main.scss"], None, Profile::Debug)
+            .await
+            .unwrap();
+        let css = debug[0].processed_relative_path.clone();
+        let map = debug[0].source_map.clone().unwrap();
+        assert_eq!(
+            map.file_name().unwrap(),
+            format!("{}.map", css.file_name().unwrap().to_string_lossy()).as_str()
+        );
+        let content = std::fs::read_to_string(config.dist_dir.join(&css)).unwrap();
+        assert!(content.ends_with(&format!(
+            "/*# sourceMappingURL={} */\n",
+            map.file_name().unwrap().to_string_lossy()
+        )));
+        assert!(config.dist_dir.join(&map).exists());
+
+        let release = process(&config, ["main.scss"], None, Profile::Release)
+            .await
+            .unwrap();
+        assert_eq!(release[0].source_map, None);
+    }
+}
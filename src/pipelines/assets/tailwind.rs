@@ -0,0 +1,272 @@
+//! Compile CSS with the Tailwind CSS standalone CLI.
+//!
+//! The flow is the same as the SASS one:
+//!
+//! - clean the intermediate folder
+//! - generate output for each entrypoint
+//! - copy all the output to the dist/css.
+//!
+//! The outputs of the previous builds are left in dist/css, next to the SASS
+//! ones: they are removed once stale, see
+//! [`super::incremental::Incremental::remove_stale_outputs`].
+//!
+//! The entrypoints live next to the SASS ones, in
+//! [`PacklerConfig::source_sass_dir`], so both pipelines can be used in the
+//! same project.
+
+use crate::common::{self};
+use crate::pipelines::assets::{sass::move_to_dist, AssetMetadata, Error, HashExclude};
+use crate::tools::{self, Application};
+use crate::PacklerConfig;
+use futures_util::future::join_all;
+use log::{error, info, warn};
+use std::iter::Iterator;
+use std::path::{Path, PathBuf};
+
+/// The `log` target of this pipeline, see [`crate::pipelines`].
+pub const LOG_TARGET: &str = "packler::tailwind";
+
+pub async fn process<E, P>(
+    config: &PacklerConfig,
+    entry_points: E,
+) -> Result<Vec<AssetMetadata>, Box<dyn std::error::Error>>
+where
+    P: AsRef<Path> + Send + Clone,
+    E: IntoIterator<Item = P>,
+{
+    let tailwind_cfg = TailwindRun {
+        config: config.clone(),
+    };
+    tailwind_cfg.start(entry_points).await
+}
+
+pub struct TailwindRun {
+    config: PacklerConfig,
+}
+
+impl TailwindRun {
+    pub fn intermediate_dir(&self) -> PathBuf {
+        self.config.intermediate_dir().join("tailwind")
+    }
+
+    pub fn clean_intermediate_folder(&self) {
+        let dir = self.intermediate_dir();
+
+        if dir.exists() && self.config.dry_run {
+            info!(target: LOG_TARGET, "DRY RUN: would remove '{}'", dir.display());
+        } else if dir.exists() {
+            match std::fs::remove_dir_all(&dir) {
+                Ok(()) => info!(target: LOG_TARGET, "TAILWIND: Intermediate folder cleared"),
+                Err(e) => warn!(
+                    target: LOG_TARGET,
+                    "TAILWIND: Could not remove intermediate folder: {e}"
+                ),
+            }
+        }
+    }
+
+    /// Spawn the pipeline for this asset type.
+    pub async fn start<P: AsRef<Path> + Send + Clone, E: IntoIterator<Item = P>>(
+        self,
+        entrypoints: E,
+    ) -> Result<Vec<AssetMetadata>, Box<dyn std::error::Error>> {
+        info!(target: LOG_TARGET, "TAILWIND: Start Tailwind Pipeline");
+
+        // Like the SASS pipeline, nothing is compiled in a dry run.
+        if self.config.dry_run {
+            for entry in entrypoints {
+                info!(
+                    target: LOG_TARGET,
+                    "DRY RUN: would compile '{}' into '{}'",
+                    entry.as_ref().display(),
+                    self.config.dist_sass_dir().display()
+                );
+            }
+            return Ok(Vec::new());
+        }
+
+        let tailwind = tools::get(
+            Application::Tailwind,
+            Some(&self.config.tailwind_version),
+            &self.config.tool_checksums,
+        )
+        .await?;
+
+        self.compile(&tailwind, entrypoints).await
+    }
+
+    /// Compile all the entrypoints with `tailwind`. Fails if any of them
+    /// fails, like the SASS pipeline.
+    async fn compile<P: AsRef<Path> + Send + Clone, E: IntoIterator<Item = P>>(
+        &self,
+        tailwind: &Path,
+        entrypoints: E,
+    ) -> Result<Vec<AssetMetadata>, Box<dyn std::error::Error>> {
+        let entrypoints: Vec<P> = entrypoints.into_iter().collect();
+
+        self.clean_intermediate_folder();
+
+        let futures = entrypoints
+            .iter()
+            .map(|entry| self.run(tailwind, entry.clone()));
+        let results = join_all(futures).await;
+
+        let mut files = Vec::new();
+        let mut failures = Vec::new();
+        for (entry, result) in entrypoints.into_iter().zip(results) {
+            match result {
+                Ok(output) => files.push(output),
+                Err(e) => {
+                    let entry = entry.as_ref().to_owned();
+                    error!(target: LOG_TARGET, "TAILWIND: '{}' failed: {e}", entry.display());
+                    failures.push((entry, e));
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(Box::new(Error::TailwindCompilationFailed(failures)));
+        }
+        Ok(files)
+    }
+
+    pub async fn run<P: AsRef<Path> + Send>(
+        &self,
+        tailwind_path: &Path,
+        entrypoint: P,
+    ) -> Result<AssetMetadata, Box<dyn std::error::Error>> {
+        let original_path = self.config.source_sass_dir().join(&entrypoint);
+
+        if !original_path.exists() {
+            error!(
+                target: LOG_TARGET,
+                "Entrypoint '{}' does not exist.",
+                &entrypoint.as_ref().display()
+            );
+            return Err(Box::new(Error::EntryPointDoesNotExist(
+                entrypoint.as_ref().display().to_string(),
+            )));
+        }
+
+        let path_str = original_path.display().to_string();
+        let entrypoint_filestem = original_path
+            .file_stem()
+            .ok_or("the entrypoint has no file name")?
+            .to_string_lossy();
+
+        let mut prehash_file_path = self.intermediate_dir();
+        prehash_file_path.push(&entrypoint);
+        prehash_file_path.set_extension("css");
+
+        let mut args = vec![
+            "-i".to_owned(),
+            path_str,
+            "-o".to_owned(),
+            prehash_file_path.display().to_string(),
+        ];
+        if let Some(tailwind_config) = &self.config.tailwind_config {
+            args.push("-c".to_owned());
+            args.push(tailwind_config.display().to_string());
+        }
+
+        // Tailwind Compile
+        log::info!(
+            target: LOG_TARGET,
+            "TAILWIND: compiling css (into {prehash_file_path:?})"
+        );
+        common::run_command(Application::Tailwind.name(), tailwind_path, &args).await?;
+        common::record_write(&prehash_file_path);
+
+        // Hash Content
+        log::info!(target: LOG_TARGET, "TAILWIND: hashing file content");
+        let css = tokio::fs::read_to_string(&prehash_file_path).await?;
+        let hash = seahash::hash(css.as_bytes());
+        let integrity = self.config.integrity_algorithm.integrity(css.as_bytes());
+
+        let logical_path = original_path.strip_prefix(&self.config.assets_source_dir)?;
+        let final_file_name = if HashExclude::new(&self.config)?.is_excluded(logical_path) {
+            format!("{entrypoint_filestem}.css")
+        } else {
+            format!("{entrypoint_filestem}-{hash:016x}.css")
+        };
+        let mut final_file_path = self.config.dist_sass_dir();
+        final_file_path.push(&entrypoint);
+        final_file_path.set_file_name(&final_file_name);
+
+        if self.config.dry_run {
+            log::info!(
+                target: LOG_TARGET,
+                "DRY RUN: would write '{}'",
+                final_file_path.display()
+            );
+        } else {
+            log::info!(
+                target: LOG_TARGET,
+                "TAILWIND: moving file to final destination '{final_file_path:?}"
+            );
+            move_to_dist(&prehash_file_path, &final_file_path)?;
+        }
+
+        let metadata = AssetMetadata::new(
+            &original_path,
+            logical_path,
+            final_file_path.strip_prefix(&self.config.dist_dir)?,
+            hash,
+            integrity,
+        );
+
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_failing_entrypoint_fails_the_pipeline() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        // Writes the output like tailwindcss (`-i <input> -o <output>`), and
+        // fails on the inputs named `broken`.
+        let tailwind = dir.path().join("tailwindcss");
+        std::fs::write(
+            &tailwind,
+            "#!/bin/sh\ncase \"$2\" in *broken*) exit 1;; esac\n\
+             mkdir -p \"$(dirname \"$4\")\"\nprintf 'a{}\\n' > \"$4\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&tailwind, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            target: dir.path().join("target"),
+            ..Default::default()
+        };
+        std::fs::create_dir_all(config.source_sass_dir()).unwrap();
+        for name in ["main.css", "broken.css"] {
+            std::fs::write(config.source_sass_dir().join(name), "@tailwind base;").unwrap();
+        }
+        let run = TailwindRun {
+            config: config.clone(),
+        };
+
+        let files = run.compile(&tailwind, ["main.css"]).await.unwrap();
+        assert!(config
+            .dist_dir
+            .join(&files[0].processed_relative_path)
+            .exists());
+
+        let error = run
+            .compile(&tailwind, ["main.css", "broken.css", "missing.css"])
+            .await
+            .unwrap_err();
+        let Some(Error::TailwindCompilationFailed(failures)) = error.downcast_ref::<Error>() else {
+            panic!("unexpected error: {error}");
+        };
+        let failed: Vec<&Path> = failures.iter().map(|(entry, _)| entry.as_path()).collect();
+        assert_eq!(failed, [Path::new("broken.css"), Path::new("missing.css")]);
+    }
+}
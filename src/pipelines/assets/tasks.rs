@@ -0,0 +1,135 @@
+//! The async assets build. The deploy tasks are in [`super::deploy`].
+//!
+//! They are public with the `async` feature, for the callers that already run
+//! tokio. The blocking variants (e.g., [`super::build_assets_blocking`]) run
+//! them on their own runtime.
+
+use super::{
+    add_unhashed_aliases, copy, critical, images, optimize, order, precompress_text_assets,
+    resolve_build_id, sass, set_public_urls, tailwind, write_metadata_file, AssetsOutput, Error,
+};
+use crate::{
+    hooks::{run_hook, Hook},
+    pipelines::assets::{incremental::Incremental, order::Pipeline},
+    PacklerConfig, PacklerParams, Profile,
+};
+use log::{debug, info, warn};
+
+/// Build the assets. With `since` (a git ref), only the assets that changed
+/// since this ref are processed again.
+pub async fn build_assets(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    since: Option<&str>,
+    profile: Profile,
+) -> Result<AssetsOutput, Error> {
+    let incremental = match since {
+        Some(git_ref) => match Incremental::since(git_ref, cfg).await {
+            Ok(incremental) => Some(incremental),
+            Err(e) => {
+                warn!("Cannot build incrementally, building everything: {e}");
+                None
+            }
+        },
+        None => None,
+    };
+
+    info!("building assets");
+    let metadata = build_assets_inner(params, cfg, incremental.as_ref(), profile).await?;
+
+    info!("writing metadata file");
+    write_metadata_file(cfg, &metadata)?;
+
+    run_hook(cfg, Hook::PostBuild)
+        .await
+        .map_err(|e| Error::HookFailed(Hook::PostBuild, e))?;
+
+    Ok(metadata)
+}
+
+/// Run all the pipelines, in the order given by [`order::build_order`].
+///
+/// Without `incremental`, the previous build is still reused for the sources
+/// whose hash did not change, see [`Incremental::from_previous_build`].
+pub async fn build_assets_inner(
+    params: &PacklerParams,
+    cfg: &PacklerConfig,
+    incremental: Option<&Incremental>,
+    profile: Profile,
+) -> Result<AssetsOutput, Error> {
+    run_hook(cfg, Hook::PreBuild)
+        .await
+        .map_err(|e| Error::HookFailed(Hook::PreBuild, e))?;
+
+    // On a fresh checkout, nothing created the dist directory yet.
+    if !cfg.dry_run {
+        std::fs::create_dir_all(&cfg.dist_dir).map_err(Error::CannotCreateDistDir)?;
+    }
+
+    let previous_build;
+    let incremental = match incremental {
+        Some(incremental) => Some(incremental),
+        None => {
+            previous_build = Incremental::from_previous_build(cfg).ok();
+            previous_build.as_ref()
+        }
+    };
+
+    let mut output = AssetsOutput {
+        build_id: resolve_build_id(cfg).await,
+        ..Default::default()
+    };
+    for pipeline in order::build_order() {
+        debug!("running the {pipeline} pipeline");
+        let failed = |e| Error::PipelineFailed(pipeline, e);
+        match pipeline {
+            Pipeline::Images => {
+                let optimizer = if cfg.optimize_images {
+                    Some(optimize::ImageOptimizer::new(cfg).await)
+                } else {
+                    None
+                };
+                output.images =
+                    images::process(cfg, incremental, optimizer.as_ref()).map_err(failed)?;
+                output.srcsets = images::srcsets(&output.images);
+            }
+            Pipeline::Copy => {
+                output.other = copy::process(cfg, incremental).map_err(failed)?;
+            }
+            Pipeline::Sass => {
+                output.sass = sass::process(cfg, &params.sass_entrypoints, incremental, profile)
+                    .await
+                    .map_err(failed)?;
+            }
+            Pipeline::Tailwind => {
+                if !params.tailwind_entrypoints.is_empty() {
+                    output.tailwind = tailwind::process(cfg, &params.tailwind_entrypoints)
+                        .await
+                        .map_err(failed)?;
+                }
+            }
+            Pipeline::CriticalCss => {
+                if let Some(critical_cfg) = &cfg.critical_css {
+                    output.critical_css = critical::process(cfg, critical_cfg, &output.sass)
+                        .await
+                        .map_err(failed)?;
+                }
+            }
+        }
+    }
+
+    if cfg.precompress {
+        precompress_text_assets(cfg, &mut output)?;
+    }
+
+    if cfg.emit_unhashed_alias {
+        add_unhashed_aliases(cfg, &mut output);
+    }
+    set_public_urls(cfg, &mut output);
+
+    if let Some(incremental) = incremental {
+        incremental.remove_stale_outputs(cfg, &output);
+    }
+
+    Ok(output)
+}
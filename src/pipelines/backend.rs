@@ -0,0 +1,98 @@
+//! Build the backend crate, see [`PacklerParams::backend_crate`], with cargo.
+
+use crate::{common, PacklerConfig, PacklerParams, Profile};
+use log::info;
+use std::fmt::Display;
+
+/// The `log` target of this pipeline, see [`crate::pipelines`].
+pub const LOG_TARGET: &str = "packler::backend";
+
+/// `cargo build -p <backend_crate>`, with `--release` for
+/// [`Profile::Release`].
+pub async fn build_backend(
+    params: &PacklerParams,
+    config: &PacklerConfig,
+    profile: Profile,
+) -> Result<(), Error> {
+    let backend_crate = params
+        .backend_crate
+        .as_deref()
+        .ok_or(Error::MissingBackendCrate)?;
+
+    let mut args = vec!["build", "-p", backend_crate];
+    if profile == Profile::Release {
+        args.push("--release");
+    }
+    cargo(config, &args).await
+}
+
+/// `cargo clean -p <backend_crate>`.
+pub async fn clean_backend(params: &PacklerParams, config: &PacklerConfig) -> Result<(), Error> {
+    let backend_crate = params
+        .backend_crate
+        .as_deref()
+        .ok_or(Error::MissingBackendCrate)?;
+
+    cargo(config, &["clean", "-p", backend_crate]).await
+}
+
+async fn cargo(config: &PacklerConfig, args: &[&str]) -> Result<(), Error> {
+    if config.dry_run {
+        info!(target: LOG_TARGET, "DRY RUN: would run 'cargo {}'", args.join(" "));
+        return Ok(());
+    }
+
+    info!(target: LOG_TARGET, "BACKEND: cargo {}", args.join(" "));
+    common::run_command("cargo", &common::cargo_path(), args)
+        .await
+        .map_err(Error::CargoFailed)
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// [`PacklerParams::backend_crate`] is not set.
+    MissingBackendCrate,
+    CargoFailed(anyhow::Error),
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CargoFailed(source) => Some(source.as_ref()),
+            Self::MissingBackendCrate => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingBackendCrate => write!(f, "The backend crate is not set"),
+            Self::CargoFailed(source) => write!(f, "cargo failed: {source}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_backend_crate_is_required() {
+        let config = PacklerConfig {
+            dry_run: true,
+            ..PacklerConfig::default()
+        };
+        let params = PacklerParams::new([""; 0], [""; 0], None);
+        assert!(matches!(
+            build_backend(&params, &config, Profile::Debug).await,
+            Err(Error::MissingBackendCrate)
+        ));
+
+        let params = PacklerParams::new([""; 0], [""; 0], Some("backend"));
+        build_backend(&params, &config, Profile::Release)
+            .await
+            .unwrap();
+        clean_backend(&params, &config).await.unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+//! Build the frontend crates, see [`PacklerParams::frontend_crates`], to wasm.
+//!
+//! The flow is as followed:
+//!
+//! - `cargo build --target wasm32-unknown-unknown` the crate, in
+//!   [`PacklerConfig::target`]
+//! - generate the JS bindings with `wasm-bindgen`, into
+//!   [`PacklerConfig::dist_frontend_dir`].
+//!
+//! [`PacklerParams::frontend_crates`]: crate::PacklerParams::frontend_crates
+
+use crate::tools::{self, Application};
+use crate::{common, PacklerConfig, Profile};
+use log::info;
+use std::fmt::Display;
+
+/// The `log` target of this pipeline, see [`crate::pipelines`].
+pub const LOG_TARGET: &str = "packler::frontend";
+
+pub const WASM_TARGET: &str = "wasm32-unknown-unknown";
+
+/// Compile `crate_name` to wasm and generate its JS bindings.
+pub async fn build_frontend(
+    config: &PacklerConfig,
+    crate_name: &str,
+    profile: Profile,
+) -> Result<(), Error> {
+    let mut args = vec!["build", "-p", crate_name, "--target", WASM_TARGET];
+    if profile == Profile::Release {
+        args.push("--release");
+    }
+    cargo(config, &args).await?;
+
+    let profile_dir = match profile {
+        Profile::Debug => "debug",
+        Profile::Release => "release",
+    };
+    let wasm = config
+        .target
+        .join(WASM_TARGET)
+        .join(profile_dir)
+        .join(format!("{}.wasm", crate_name.replace('-', "_")));
+    let out_dir = config.dist_frontend_dir(crate_name);
+
+    if config.dry_run {
+        info!(
+            target: LOG_TARGET,
+            "DRY RUN: would generate the bindings of '{}' into '{}'",
+            wasm.display(),
+            out_dir.display()
+        );
+        return Ok(());
+    }
+
+    // The CLI must match the wasm-bindgen version the crate depends on.
+    let version = wasm_bindgen_version();
+    let wasm_bindgen = tools::get(
+        Application::WasmBindgen,
+        version.as_deref(),
+        &config.tool_checksums,
+    )
+    .await
+    .map_err(Error::WasmBindgenFailed)?;
+
+    if out_dir.exists() {
+        std::fs::remove_dir_all(&out_dir).map_err(Error::CannotCleanOutput)?;
+    }
+
+    info!(target: LOG_TARGET, "FRONTEND: generating the bindings of '{crate_name}'");
+    let out_dir_arg = out_dir.display().to_string();
+    let wasm_arg = wasm.display().to_string();
+    let args = [
+        "--target",
+        "web",
+        "--no-typescript",
+        "--out-dir",
+        &out_dir_arg,
+        "--out-name",
+        crate_name,
+        &wasm_arg,
+    ];
+    common::run_command(Application::WasmBindgen.name(), &wasm_bindgen, &args)
+        .await
+        .map_err(Error::WasmBindgenFailed)?;
+
+    for output in common::walk_dir(&out_dir, true).flatten() {
+        common::record_write(output.path());
+    }
+    Ok(())
+}
+
+/// `cargo clean -p` the crate and remove its bindings.
+pub async fn clean_frontend(config: &PacklerConfig, crate_name: &str) -> Result<(), Error> {
+    cargo(
+        config,
+        &["clean", "-p", crate_name, "--target", WASM_TARGET],
+    )
+    .await?;
+
+    let out_dir = config.dist_frontend_dir(crate_name);
+    if out_dir.exists() && config.dry_run {
+        info!(target: LOG_TARGET, "DRY RUN: would remove '{}'", out_dir.display());
+    } else if out_dir.exists() {
+        std::fs::remove_dir_all(&out_dir).map_err(Error::CannotCleanOutput)?;
+    }
+    Ok(())
+}
+
+/// Run cargo with `--target-dir` [`PacklerConfig::target`]: the wasm is looked
+/// for there, whatever the `CARGO_TARGET_DIR` or `build.target-dir` of the
+/// workspace.
+async fn cargo(config: &PacklerConfig, args: &[&str]) -> Result<(), Error> {
+    let target_dir = config.target.display().to_string();
+    let args = [args, &["--target-dir", &target_dir]].concat();
+
+    if config.dry_run {
+        info!(target: LOG_TARGET, "DRY RUN: would run 'cargo {}'", args.join(" "));
+        return Ok(());
+    }
+
+    info!(target: LOG_TARGET, "FRONTEND: cargo {}", args.join(" "));
+    common::run_command("cargo", &common::cargo_path(), &args)
+        .await
+        .map_err(Error::CargoFailed)
+}
+
+/// The version of the `wasm-bindgen` dependency of the workspace, if any.
+fn wasm_bindgen_version() -> Option<String> {
+    crate::cargo_metadata()
+        .packages
+        .iter()
+        .find(|package| package.name == "wasm-bindgen")
+        .map(|package| package.version.to_string())
+}
+
+#[derive(Debug)]
+pub enum Error {
+    CargoFailed(anyhow::Error),
+    WasmBindgenFailed(anyhow::Error),
+    CannotCleanOutput(std::io::Error),
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CargoFailed(source) | Self::WasmBindgenFailed(source) => Some(source.as_ref()),
+            Self::CannotCleanOutput(source) => Some(source),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CargoFailed(source) => write!(f, "cargo failed: {source}"),
+            Self::WasmBindgenFailed(source) => write!(f, "wasm-bindgen failed: {source}"),
+            Self::CannotCleanOutput(source) => {
+                write!(f, "Cannot remove the previous bindings: {source}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn dry_run_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            dist_dir: dir.path().join("dist"),
+            dry_run: true,
+            ..PacklerConfig::default()
+        };
+        assert_eq!(
+            config.dist_frontend_dir("my-app"),
+            dir.path().join("dist").join("wasm").join("my-app")
+        );
+
+        build_frontend(&config, "my-app", Profile::Release)
+            .await
+            .unwrap();
+        clean_frontend(&config, "my-app").await.unwrap();
+        assert!(!config.dist_dir.exists());
+    }
+}
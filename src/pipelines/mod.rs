@@ -1 +1,18 @@
+//! The pipelines turn the sources into the files served to the users.
+//!
+//! Each pipeline logs with its own `log` target, so their logs can be filtered
+//! or routed separately (e.g., `RUST_LOG=packler::sass=debug`):
+//!
+//! - `packler::images`: the images pipeline (and their placeholders and
+//!   optimization)
+//! - `packler::sass`: the SASS compilation
+//! - `packler::tailwind`: the Tailwind CSS compilation
+//! - `packler::critical`: the critical CSS extraction
+//! - `packler::compress`: the pre-compressed variants of the text assets
+//! - `packler::bucket`: the uploads to the assets bucket
+//! - `packler::backend`: the cargo commands of the backend crate
+//! - `packler::frontend`: the wasm build of the frontend crates
+
 pub mod assets;
+pub mod backend;
+pub mod frontend;
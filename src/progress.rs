@@ -0,0 +1,146 @@
+//! Progress of the long phases of a build or a deploy, e.g., hundreds of
+//! images to process or of objects to upload.
+//!
+//! The pipelines report it to the callback of [`set_progress_callback`], if
+//! any: the logs are the only feedback otherwise. With the `progress` feature,
+//! [`init_progress_bars`] shows it as progress bars.
+
+use once_cell::sync::Lazy;
+use std::sync::{PoisonError, RwLock};
+
+type Callback = Box<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// The callback of [`set_progress_callback`].
+static CALLBACK: Lazy<RwLock<Option<Callback>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// The images hashed and written to the dist directory.
+    Images,
+    /// The files of the [`PacklerConfig::copy_dirs`] copied.
+    ///
+    /// [`PacklerConfig::copy_dirs`]: crate::PacklerConfig::copy_dirs
+    Copy,
+    /// The SASS entrypoints compiled.
+    Sass,
+    /// The objects uploaded, to all the buckets.
+    Upload,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Images => "images",
+            Self::Copy => "copy",
+            Self::Sass => "sass",
+            Self::Upload => "upload",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// `total` more steps in `phase`, e.g., the images found. Sent again by
+    /// each bucket for [`Phase::Upload`].
+    Start { phase: Phase, total: usize },
+    /// One step of `phase` is done, successfully or not.
+    Advance { phase: Phase },
+}
+
+/// Call `callback` with the progress of the pipelines, instead of the
+/// previous callback. It is called from the threads of the pipelines: it
+/// should be quick.
+pub fn set_progress_callback(callback: impl Fn(ProgressEvent) + Send + Sync + 'static) {
+    *CALLBACK.write().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(callback));
+}
+
+/// `total` more steps in `phase`.
+pub fn start(phase: Phase, total: usize) {
+    report(ProgressEvent::Start { phase, total });
+}
+
+/// One more step of `phase` is done.
+pub fn advance(phase: Phase) {
+    report(ProgressEvent::Advance { phase });
+}
+
+fn report(event: ProgressEvent) {
+    if let Some(callback) = CALLBACK
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+    {
+        callback(event);
+    }
+}
+
+/// Show the progress as bars on stderr, one per phase. Does nothing when
+/// stderr is not a terminal (e.g., in a CI job): the logs are enough there.
+///
+/// Returns whether the bars are shown.
+#[cfg(feature = "progress")]
+pub fn init_progress_bars() -> bool {
+    use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+    use std::{collections::HashMap, io::IsTerminal, sync::Mutex};
+
+    if !std::io::stderr().is_terminal() {
+        return false;
+    }
+
+    let style = ProgressStyle::with_template("{prefix:>8} [{bar:40}] {pos}/{len} ({elapsed})")
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("=> ");
+    let bars = MultiProgress::new();
+    let by_phase: Mutex<HashMap<Phase, ProgressBar>> = Mutex::default();
+    set_progress_callback(move |event| {
+        let mut by_phase = by_phase.lock().unwrap_or_else(PoisonError::into_inner);
+        match event {
+            ProgressEvent::Start { total: 0, .. } => {}
+            ProgressEvent::Start { phase, total } => {
+                let bar = by_phase.entry(phase).or_insert_with(|| {
+                    let bar = ProgressBar::new(0)
+                        .with_style(style.clone())
+                        .with_prefix(phase.name());
+                    bars.add(bar)
+                });
+                bar.inc_length(total as u64);
+            }
+            ProgressEvent::Advance { phase } => {
+                let Some(bar) = by_phase.get(&phase) else {
+                    return;
+                };
+                bar.inc(1);
+                // Done: the next build gets a new bar.
+                if Some(bar.position()) >= bar.length() {
+                    bar.finish();
+                    by_phase.remove(&phase);
+                }
+            }
+        }
+    });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn events_reach_the_callback() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let received = events.clone();
+        set_progress_callback(move |event| received.lock().unwrap().push(event));
+
+        start(Phase::Sass, 2);
+        advance(Phase::Sass);
+
+        // The other tests may report their progress too.
+        let events = events.lock().unwrap();
+        assert!(events.contains(&ProgressEvent::Start {
+            phase: Phase::Sass,
+            total: 2
+        }));
+        assert!(events.contains(&ProgressEvent::Advance { phase: Phase::Sass }));
+    }
+}
@@ -3,7 +3,7 @@
 //! pipeline.
 //! Borrowed from Trunk!
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::Metadata;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
@@ -12,6 +12,7 @@ use anyhow::{bail, ensure, Context, Result};
 use directories::ProjectDirs;
 use futures_util::stream::StreamExt;
 use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
 use tokio::fs::{self, File};
 use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
@@ -24,19 +25,32 @@ use self::archive::Archive;
 pub enum Application {
     /// Dart Sass
     Sass,
+    /// critical, to extract the above-the-fold CSS of a page.
+    Critical,
+    /// The Tailwind CSS standalone CLI.
+    Tailwind,
     /// wasm-bindgen for generating the JS bindings.
     WasmBindgen,
     /// wasm-opt to improve performance and size of the output file further.
     WasmOpt,
+    /// oxipng, to optimize the PNG images losslessly.
+    Oxipng,
+    /// jpegtran (from libjpeg-turbo or mozjpeg), to optimize the JPEG images
+    /// losslessly.
+    Jpegtran,
 }
 
 impl Application {
     /// Base name of the executable without extension.
-    pub(crate) fn name(&self) -> &str {
+    pub(crate) fn name(&self) -> &'static str {
         match self {
             Self::Sass => "sass",
+            Self::Critical => "critical",
+            Self::Tailwind => "tailwindcss",
             Self::WasmBindgen => "wasm-bindgen",
             Self::WasmOpt => "wasm-opt",
+            Self::Oxipng => "oxipng",
+            Self::Jpegtran => "jpegtran",
         }
     }
 
@@ -45,14 +59,22 @@ impl Application {
         if cfg!(target_os = "windows") {
             match self {
                 Self::Sass => "sass.bat",
+                Self::Critical => "critical.cmd",
+                Self::Tailwind => "tailwindcss.exe",
                 Self::WasmBindgen => "wasm-bindgen.exe",
                 Self::WasmOpt => "bin/wasm-opt.exe",
+                Self::Oxipng => "oxipng.exe",
+                Self::Jpegtran => "jpegtran.exe",
             }
         } else {
             match self {
                 Self::Sass => "sass",
+                Self::Critical => "critical",
+                Self::Tailwind => "tailwindcss",
                 Self::WasmBindgen => "wasm-bindgen",
                 Self::WasmOpt => "bin/wasm-opt",
+                Self::Oxipng => "oxipng",
+                Self::Jpegtran => "jpegtran",
             }
         }
     }
@@ -63,13 +85,13 @@ impl Application {
             Self::Sass => {
                 if cfg!(target_os = "windows") {
                     &["src/dart.exe", "src/sass.snapshot"]
-                } else if cfg!(target_os = "macos") {
-                    &["src/dart", "src/sass.snapshot"]
                 } else {
                     &["src/dart", "src/sass.snapshot"]
                 }
             }
-            Self::WasmBindgen => &[],
+            Self::Critical | Self::Tailwind | Self::WasmBindgen | Self::Oxipng | Self::Jpegtran => {
+                &[]
+            }
             Self::WasmOpt => {
                 if cfg!(target_os = "macos") {
                     &["lib/libbinaryen.dylib"]
@@ -84,13 +106,21 @@ impl Application {
     fn default_version(&self) -> &str {
         match self {
             Self::Sass => "1.50.0",
+            Self::Critical => "5.1.1",
+            Self::Tailwind => "3.3.2",
             Self::WasmBindgen => "0.2.80",
             Self::WasmOpt => "version_105",
+            Self::Oxipng => "9.1.2",
+            Self::Jpegtran => "3.0.0",
         }
     }
 
     /// Direct URL to the release of an application for download.
-    fn url(&self, version: &str) -> Result<String> {
+    ///
+    /// `platform` replaces the `<os>-<arch>` part of the Sass and Tailwind
+    /// artifact names (e.g., `linux-arm64-musl`) when the detected one is
+    /// wrong.
+    pub(crate) fn url(&self, version: &str, platform: Option<&str>) -> Result<String> {
         let target_os = if cfg!(target_os = "windows") {
             "windows"
         } else if cfg!(target_os = "macos") {
@@ -109,6 +139,26 @@ impl Application {
             bail!("unsupported target architecture")
         };
 
+        log::debug!(
+            "resolved target for {}: {target_os}-{target_arch} (platform override: {platform:?})",
+            self.name()
+        );
+
+        if let Some(platform) = platform {
+            let ext = |windows_ext, ext| {
+                if platform.starts_with("windows") {
+                    windows_ext
+                } else {
+                    ext
+                }
+            };
+            return match self {
+                Self::Sass => Ok(format!("https://github.com/sass/dart-sass/releases/download/{version}/dart-sass-{version}-{platform}.{}", ext("zip", "tar.gz"))),
+                Self::Tailwind => Ok(format!("https://github.com/tailwindlabs/tailwindcss/releases/download/v{version}/tailwindcss-{platform}{}", ext(".exe", ""))),
+                _ => bail!("{} does not support a platform override", self.name()),
+            };
+        }
+
         Ok(match self {
             Self::Sass => match (target_os, target_arch) {
               ("windows", "x86_64") => format!("https://github.com/sass/dart-sass/releases/download/{version}/dart-sass-{version}-windows-x64.zip"),
@@ -117,6 +167,17 @@ impl Application {
               _ => bail!("Unable to download Sass for {target_os} {target_arch}")
             },
 
+            // critical is only distributed through npm, there is no standalone
+            // release we could download.
+            Self::Critical => bail!("critical must be installed on the system (npm install -g critical@{version})"),
+
+            // Not an archive, the executable itself.
+            Self::Tailwind => format!(
+                "https://github.com/tailwindlabs/tailwindcss/releases/download/v{version}/tailwindcss-{target_os}-{arch}{ext}",
+                arch = if target_arch == "x86_64" { "x64" } else { "arm64" },
+                ext = if target_os == "windows" { ".exe" } else { "" },
+            ),
+
             Self::WasmBindgen => format!(
                 "https://github.com/rustwasm/wasm-bindgen/releases/download/{version}/wasm-bindgen-{version}-x86_64-{os}.tar.gz",
                 os = match target_os {
@@ -129,7 +190,16 @@ impl Application {
             Self::WasmOpt => match (target_os, target_arch) {
               ("macos", "aarch64") => format!("https://github.com/WebAssembly/binaryen/releases/download/{version}/binaryen-{version}-arm64-macos.tar.gz"),
               _ => format!("https://github.com/WebAssembly/binaryen/releases/download/{version}/binaryen-{version}-{target_arch}-{target_os}.tar.gz")
-            }
+            },
+
+            Self::Oxipng => match target_os {
+              "windows" => format!("https://github.com/shssoichiro/oxipng/releases/download/v{version}/oxipng-{version}-{target_arch}-pc-windows-msvc.zip"),
+              "macos" => format!("https://github.com/shssoichiro/oxipng/releases/download/v{version}/oxipng-{version}-{target_arch}-apple-darwin.tar.gz"),
+              _ => format!("https://github.com/shssoichiro/oxipng/releases/download/v{version}/oxipng-{version}-{target_arch}-unknown-linux-musl.tar.gz"),
+            },
+
+            // libjpeg-turbo and mozjpeg only release installers and packages.
+            Self::Jpegtran => bail!("jpegtran must be installed on the system (e.g., the libjpeg-turbo-progs package)"),
         })
     }
 
@@ -137,8 +207,12 @@ impl Application {
     fn version_test(&self) -> &'static str {
         match self {
             Application::Sass => "--version",
+            Application::Critical => "--version",
+            Application::Tailwind => "--help",
             Application::WasmBindgen => "--version",
             Application::WasmOpt => "--version",
+            Application::Oxipng => "--version",
+            Application::Jpegtran => "-version",
         }
     }
 
@@ -146,12 +220,17 @@ impl Application {
     fn format_version_output(&self, text: &str) -> Result<String> {
         let text = text.trim();
         let formatted_version = match self {
-            Application::Sass => text
+            Application::Sass | Application::Critical => text
                 .lines()
                 .next()
                 .with_context(|| format!("missing or malformed version output: {}", text))?
                 .to_owned(),
-            Application::WasmBindgen => text
+            Application::Tailwind => text
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("tailwindcss v"))
+                .with_context(|| format!("missing or malformed version output: {}", text))?
+                .to_owned(),
+            Application::WasmBindgen | Application::Oxipng => text
                 .split(' ')
                 .nth(1)
                 .with_context(|| format!("missing or malformed version output: {}", text))?
@@ -162,6 +241,8 @@ impl Application {
                     .nth(2)
                     .with_context(|| format!("missing or malformed version output: {}", text))?
             ),
+            // `-version` prints to stderr: any system installed jpegtran is used.
+            Application::Jpegtran => text.to_owned(),
         };
         Ok(formatted_version)
     }
@@ -175,7 +256,7 @@ static GLOBAL_APP_CACHE: Lazy<Mutex<AppCache>> = Lazy::new(|| Mutex::new(AppCach
 /// keeping track of what has already been installed.
 ///
 /// This cache doesn't keep track of any system-installed tools
-struct AppCache(HashMap<(Application, String), OnceCell<()>>);
+struct AppCache(HashMap<(Application, String, Option<String>), OnceCell<()>>);
 
 impl AppCache {
     fn new() -> Self {
@@ -188,16 +269,18 @@ impl AppCache {
         &mut self,
         app: Application,
         version: &str,
+        platform: Option<&str>,
+        checksums: &BTreeMap<String, String>,
         app_dir: PathBuf,
     ) -> Result<()> {
         let cached = self
             .0
-            .entry((app, version.to_owned()))
-            .or_insert_with(OnceCell::new);
+            .entry((app, version.to_owned(), platform.map(str::to_owned)))
+            .or_default();
 
         cached
             .get_or_try_init(|| async move {
-                let path = download(app, version)
+                let path = download(app, version, platform, checksums)
                     .await
                     .context("failed downloading release archive")?;
 
@@ -217,7 +300,25 @@ impl AppCache {
 }
 
 /// Locate the given application and download it if missing.
-pub async fn get(app: Application, version: Option<&str>) -> Result<PathBuf> {
+///
+/// A download is checked against its SHA-256 in `checksums`, keyed by the file
+/// name of the artifact, see [`crate::PacklerConfig::tool_checksums`].
+pub async fn get(
+    app: Application,
+    version: Option<&str>,
+    checksums: &BTreeMap<String, String>,
+) -> Result<PathBuf> {
+    get_for_platform(app, version, None, checksums).await
+}
+
+/// Like [`get`], but download the artifact of `platform` instead of the
+/// detected one, see [`Application::url`].
+pub async fn get_for_platform(
+    app: Application,
+    version: Option<&str>,
+    platform: Option<&str>,
+    checksums: &BTreeMap<String, String>,
+) -> Result<PathBuf> {
     if let Some((path, _version)) = find_system(app, version).await {
         log::info!("Using system installed {}", app.name());
         return Ok(path);
@@ -225,7 +326,10 @@ pub async fn get(app: Application, version: Option<&str>) -> Result<PathBuf> {
 
     let cache_dir = cache_dir().await?;
     let version = version.unwrap_or_else(|| app.default_version());
-    let app_dir = cache_dir.join(format!("{}-{}", app.name(), version));
+    let app_dir = match platform {
+        Some(platform) => cache_dir.join(format!("{}-{}-{}", app.name(), version, platform)),
+        None => cache_dir.join(format!("{}-{}", app.name(), version)),
+    };
     let bin_path = app_dir.join(app.path());
 
     // This will prevent a new installation if the binary is already there...
@@ -240,13 +344,41 @@ pub async fn get(app: Application, version: Option<&str>) -> Result<PathBuf> {
         GLOBAL_APP_CACHE
             .lock()
             .await
-            .install_once(app, version, app_dir)
+            .install_once(app, version, platform, checksums, app_dir)
             .await?;
+
+        check_runs(app, &bin_path).await?;
     }
 
     Ok(bin_path)
 }
 
+/// Make sure a freshly downloaded application can run here: the artifact of
+/// another platform may have been downloaded.
+async fn check_runs(app: Application, bin_path: &Path) -> Result<()> {
+    ensure!(
+        is_executable(bin_path).await?,
+        "the downloaded {} ({}) is not executable",
+        app.name(),
+        bin_path.display()
+    );
+
+    if let Err(e) = Command::new(bin_path)
+        .arg(app.version_test())
+        .output()
+        .await
+    {
+        bail!(
+            "the downloaded {} ({}) cannot run on this platform, it may be the artifact of \
+             another one (see `sass_platform_override`): {e}",
+            app.name(),
+            bin_path.display()
+        );
+    }
+
+    Ok(())
+}
+
 /// Try to find a globally system installed version of the application and
 /// ensure it is the needed release version.
 async fn find_system(app: Application, version: Option<&str>) -> Option<(PathBuf, String)> {
@@ -277,7 +409,15 @@ async fn find_system(app: Application, version: Option<&str>) -> Option<(PathBuf
 
 /// Download a file from its remote location in the given version, extract it and make it ready for
 /// execution at the given location.
-async fn download(app: Application, version: &str) -> Result<PathBuf> {
+///
+/// Fails, before anything is extracted, if the download does not match its
+/// checksum in `checksums`.
+async fn download(
+    app: Application,
+    version: &str,
+    platform: Option<&str>,
+    checksums: &BTreeMap<String, String>,
+) -> Result<PathBuf> {
     log::info!("downloading {} {version}", app.name());
 
     let cache_dir = cache_dir()
@@ -288,34 +428,74 @@ async fn download(app: Application, version: &str) -> Result<PathBuf> {
         .await
         .context("failed creating temporary output file")?;
 
-    let resp = reqwest::get(app.url(version)?)
+    let url = app.url(version, platform)?;
+    log::debug!("downloading {} from {url}", app.name());
+    let resp = reqwest::get(&url)
         .await
-        .context("error sending HTTP request")?;
+        .with_context(|| format!("error sending HTTP request to {url}"))?;
     ensure!(
         resp.status().is_success(),
-        "error downloading archive file: {:?}\n{}",
-        resp.status(),
-        app.url(version)?
+        "could not download {} {version} from {url}: {} (is {version} a released version?)",
+        app.name(),
+        resp.status()
     );
+    let mut hasher = Sha256::new();
     let mut res_bytes = resp.bytes_stream();
     while let Some(chunk_res) = res_bytes.next().await {
         let chunk = chunk_res.context("error reading chunk from download")?;
-        let _res = file.write(chunk.as_ref()).await;
+        hasher.update(&chunk);
+        file.write_all(chunk.as_ref())
+            .await
+            .context("failed writing the download")?;
+    }
+
+    let artifact = url.rsplit('/').next().unwrap_or(&url);
+    let checksum: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    if let Err(e) = verify_checksum(artifact, &checksum, checksums.get(artifact)) {
+        // Never install it, not even by mistake.
+        let _ = fs::remove_file(&temp_out).await;
+        return Err(e);
     }
 
     Ok(temp_out)
 }
 
+/// Check the SHA-256 of a downloaded `artifact` against the `expected` one.
+/// Without one, the checksum is logged so that it can be pinned.
+fn verify_checksum(artifact: &str, checksum: &str, expected: Option<&String>) -> Result<()> {
+    match expected {
+        Some(expected) => ensure!(
+            expected.eq_ignore_ascii_case(checksum),
+            "the checksum of {artifact} does not match: expected {expected}, got {checksum}"
+        ),
+        None => log::warn!(
+            "no checksum pinned for {artifact} (see `tool_checksums`), its SHA-256 is {checksum}"
+        ),
+    }
+    Ok(())
+}
+
 /// Install an application from a downloaded archive locating and copying it to the given target
 /// location.
-
 async fn install(app: Application, archive_file: File, target: PathBuf) -> Result<()> {
     log::info!("installing {}", app.name());
 
     let archive_file = archive_file.into_std().await;
 
+    if app == Application::Tailwind {
+        return tokio::task::spawn_blocking(move || {
+            archive::install_executable(archive_file, app.path(), &target)
+        })
+        .await?;
+    }
+
     tokio::task::spawn_blocking(move || {
-        let mut archive = if app == Application::Sass && cfg!(target_os = "windows") {
+        let is_zip = matches!(app, Application::Sass | Application::Oxipng);
+        let mut archive = if is_zip && cfg!(target_os = "windows") {
             Archive::new_zip(archive_file)?
         } else {
             Archive::new_tar_gz(archive_file)
@@ -419,7 +599,7 @@ mod archive {
     fn find_tar_entry(
         archive: &mut TarArchive<impl Read>,
         path: impl AsRef<Path>,
-    ) -> Result<Option<TarEntry<impl Read>>> {
+    ) -> Result<Option<TarEntry<'_, impl Read>>> {
         let entries = archive
             .entries()
             .context("failed getting archive entries")?;
@@ -461,6 +641,13 @@ mod archive {
         Ok(None)
     }
 
+    /// Install a downloaded file that is not an archive but the executable
+    /// itself.
+    pub fn install_executable(mut downloaded: File, file: &str, target: &Path) -> Result<()> {
+        let mut out_file = extract_file(&mut downloaded, file, target)?;
+        set_file_permissions(&mut out_file, 0o755)
+    }
+
     fn extract_file(mut read: impl Read, file: &str, target: &Path) -> Result<File> {
         let out = target.join(file);
 
@@ -529,7 +716,7 @@ mod tests {
             Application::WasmBindgen,
             Application::WasmOpt,
         ] {
-            let path = download(app, app.default_version())
+            let path = download(app, app.default_version(), None, &BTreeMap::new())
                 .await
                 .context("error downloading app")?;
             let file = File::open(&path).await.context("error opening file")?;
@@ -541,6 +728,26 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn platform_override_url() -> Result<()> {
+        let url = Application::Sass.url("1.59.3", Some("linux-arm64-musl"))?;
+        ensure!(url.ends_with("/1.59.3/dart-sass-1.59.3-linux-arm64-musl.tar.gz"));
+        let url = Application::Sass.url("1.59.3", Some("windows-x64"))?;
+        ensure!(url.ends_with("/1.59.3/dart-sass-1.59.3-windows-x64.zip"));
+        ensure!(Application::WasmOpt
+            .url("version_105", Some("linux-x64"))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn downloads_must_match_their_checksum() {
+        let checksum = "47dd6a1a0d1b9a0f1e1b0e13b6e0f8a3c2b5f6b8a1d4c3e2f1a0b9c8d7e6f5a4";
+        assert!(verify_checksum("sass.tar.gz", checksum, None).is_ok());
+        assert!(verify_checksum("sass.tar.gz", checksum, Some(&checksum.to_uppercase())).is_ok());
+        assert!(verify_checksum("sass.tar.gz", checksum, Some(&"0".repeat(64))).is_err());
+    }
+
     macro_rules! table_test_format_version {
         ($name:ident, $app:expr, $input:literal, $expect:literal) => {
             #[test]
@@ -589,4 +796,15 @@ mod tests {
     );
 
     table_test_format_version!(sass_pre_compiled, Application::Sass, "1.37.5", "1.37.5");
+
+    table_test_format_version!(
+        tailwind_help,
+        Application::Tailwind,
+        "\ntailwindcss v3.3.2\n\nUsage:\n   tailwindcss build [options]",
+        "3.3.2"
+    );
+
+    table_test_format_version!(critical_npm, Application::Critical, "5.1.1\n", "5.1.1");
+
+    table_test_format_version!(oxipng, Application::Oxipng, "oxipng 9.1.2\n", "9.1.2");
 }
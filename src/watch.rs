@@ -0,0 +1,213 @@
+//! Wait for the changes of the watched sources, see `build --watch`.
+
+use crate::{common, PacklerConfig};
+use globset::GlobSet;
+use log::{error, trace};
+use notify::{PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+/// The events sent by the [`notify`] watcher.
+pub type Events = UnboundedReceiver<notify::Result<notify::Event>>;
+
+/// Call `on_change` with the changed paths whenever some relevant files under
+/// `path` changed, once they settled for `debounce`. See [`next_changes`].
+///
+/// With `poll`, the changes are found by scanning `path` at this interval, see
+/// [`PacklerConfig::watch_poll_ms`].
+///
+/// Only returns once the watcher is gone, or if it cannot start.
+pub async fn watch<F, Fut>(
+    path: &Path,
+    debounce: Duration,
+    poll: Option<Duration>,
+    filter: &ChangeFilter<'_>,
+    mut on_change: F,
+) -> notify::Result<()>
+where
+    F: FnMut(Vec<PathBuf>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let (_watcher, mut events) = watcher(path, poll)?;
+    while let Some(changed) = next_changes(&mut events, debounce, filter).await {
+        on_change(changed).await;
+    }
+    Ok(())
+}
+
+/// Watch `path` recursively, with the events of the OS or, with `poll`, by
+/// scanning it at this interval. The events are sent until the watcher is
+/// dropped.
+pub fn watcher(
+    path: &Path,
+    poll: Option<Duration>,
+) -> notify::Result<(Box<dyn Watcher + Send>, Events)> {
+    let (tx, rx) = unbounded_channel();
+    let handler = move |event| {
+        // The receiver is gone when the watch stopped.
+        let _ = tx.send(event);
+    };
+    let mut watcher: Box<dyn Watcher + Send> = match poll {
+        Some(interval) => {
+            let config = notify::Config::default().with_poll_interval(interval);
+            Box::new(PollWatcher::new(handler, config)?)
+        }
+        None => Box::new(RecommendedWatcher::new(handler, notify::Config::default())?),
+    };
+    watcher.watch(path, RecursiveMode::Recursive)?;
+    Ok((watcher, rx))
+}
+
+/// How to fix a watch error, when it is a known one.
+pub fn error_hint(error: &notify::Error) -> Option<&'static str> {
+    match &error.kind {
+        notify::ErrorKind::MaxFilesWatch => Some(
+            "Too many files are watched: on Linux, raise the inotify limit, e.g., \
+             `sudo sysctl fs.inotify.max_user_watches=524288`",
+        ),
+        notify::ErrorKind::PathNotFound => Some("Check that the watched path exists"),
+        notify::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Some("Check that the watched path exists")
+        }
+        _ => None,
+    }
+}
+
+/// Which changes trigger a rebuild.
+pub struct ChangeFilter<'a> {
+    config: &'a PacklerConfig,
+    ignore: GlobSet,
+}
+
+impl<'a> ChangeFilter<'a> {
+    pub fn new(config: &'a PacklerConfig) -> Result<Self, crate::config::Error> {
+        Ok(Self {
+            config,
+            ignore: config.watch_ignore_set()?,
+        })
+    }
+
+    /// Our own outputs and the ignored files (e.g., editor swap files) never
+    /// trigger a rebuild.
+    pub fn is_relevant(&self, path: &Path) -> bool {
+        let ignored = path
+            .file_name()
+            .is_some_and(|name| self.ignore.is_match(name));
+        !(ignored || self.config.is_output_path(path) || common::is_recent_write(path))
+    }
+}
+
+/// Block until some relevant files changed, then until none changed for
+/// `debounce`: a burst of changes is reported once, after it settles.
+///
+/// Returns the changed paths, or `None` once the watcher is gone.
+pub async fn next_changes(
+    events: &mut Events,
+    debounce: Duration,
+    filter: &ChangeFilter<'_>,
+) -> Option<Vec<PathBuf>> {
+    let mut changed: Vec<PathBuf> = Vec::new();
+
+    loop {
+        let event = if changed.is_empty() {
+            events.recv().await?
+        } else {
+            match tokio::time::timeout(debounce, events.recv()).await {
+                Ok(Some(event)) => event,
+                // Also report the last burst once the watcher is gone.
+                Ok(None) | Err(_) => return Some(changed),
+            }
+        };
+
+        match event {
+            Ok(event) => {
+                let relevant: Vec<PathBuf> = event
+                    .paths
+                    .into_iter()
+                    .filter(|path| filter.is_relevant(path))
+                    .collect();
+                if relevant.is_empty() {
+                    trace!("Ignore event on {:?}.", event.kind);
+                }
+                for path in relevant {
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+            }
+            Err(e) => match error_hint(&e) {
+                Some(hint) => error!("Watch error: {e}. {hint}"),
+                None => error!("Watch error: {e}"),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::{Event, EventKind};
+
+    #[tokio::test]
+    async fn bursts_of_changes_are_reported_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PacklerConfig {
+            assets_source_dir: dir.path().join("assets"),
+            dist_dir: dir.path().join("dist"),
+            target: dir.path().join("target"),
+            ..PacklerConfig::default()
+        };
+        let filter = ChangeFilter::new(&config).unwrap();
+        let debounce = Duration::from_millis(20);
+        let (tx, mut rx) = unbounded_channel();
+        let send = |path: PathBuf| {
+            let event = Event::new(EventKind::Any).add_path(path);
+            tx.send(Ok(event)).unwrap();
+        };
+
+        let scss = config.assets_source_dir.join("css").join("app.scss");
+        send(config.assets_source_dir.join("css").join(".app.scss.swp"));
+        send(scss.clone());
+        send(config.dist_dir.join("assets.json"));
+        send(scss.clone());
+        assert_eq!(
+            next_changes(&mut rx, debounce, &filter).await,
+            Some(vec![scss])
+        );
+
+        drop(tx);
+        assert_eq!(next_changes(&mut rx, debounce, &filter).await, None);
+    }
+
+    #[test]
+    fn watch_errors_have_a_hint() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = watcher(&dir.path().join("missing"), None).err().unwrap();
+        assert_eq!(
+            error_hint(&error),
+            Some("Check that the watched path exists")
+        );
+
+        let error = notify::Error::new(notify::ErrorKind::MaxFilesWatch);
+        assert!(error_hint(&error).unwrap().contains("max_user_watches"));
+    }
+
+    #[tokio::test]
+    async fn polling_finds_the_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_watcher, mut events) = watcher(dir.path(), Some(Duration::from_millis(10))).unwrap();
+
+        let file = dir.path().join("app.scss");
+        std::fs::write(&file, "body {}").unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.paths, [file]);
+    }
+}